@@ -0,0 +1,164 @@
+pub mod c;
+pub mod rust;
+pub mod wat;
+pub mod x86_64;
+
+use anyhow::{anyhow, Result};
+
+/* langage dans lequel on produit le programme, le Brainfuck restant la cible par défaut */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    Bf,
+    C,
+    Rust,
+    Wat,
+    X86_64,
+}
+
+impl Target {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "bf" => Ok(Self::Bf),
+            "c" => Ok(Self::C),
+            "rust" => Ok(Self::Rust),
+            "wat" => Ok(Self::Wat),
+            "x86_64-asm" => Ok(Self::X86_64),
+            _ => Err(anyhow!("unknown target {}", name)),
+        }
+    }
+
+    #[inline]
+    pub const fn extension(&self) -> &'static str {
+        match self {
+            Self::Bf => "bf",
+            Self::C => "c",
+            Self::Rust => "rs",
+            Self::Wat => "wat",
+            Self::X86_64 => "s",
+        }
+    }
+}
+
+/* nombre de cellules de la bande par défaut, comme la plupart des interpréteurs */
+pub const TAPE_SIZE: usize = 30000;
+
+/* fonction permettant d'ajouter une ligne indentée au code produit */
+pub(crate) fn push_line(res: &mut String, depth: usize, line: &str) {
+    res.push_str(&"    ".repeat(depth));
+    res.push_str(line);
+    res.push('\n');
+}
+
+/* outils communs aux tests des cibles: des programmes d'exemple et l'exécution d'un programme compilé par un outil externe */
+#[cfg(test)]
+pub(crate) mod testing {
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use std::process::{Command, Stdio};
+
+    use crate::builtin::{expand_builtin, Builtin};
+    use crate::code_checker::{transform_goto, Outputpointer};
+    use crate::function::FlattenedInstruction::{self, *};
+    use crate::interpreter;
+    use crate::layout::Layout;
+    use crate::optimizer::{OptLevel, PassManager};
+    use crate::producer::produce;
+
+    /* programmes d'exemple avec leur entrée: chaque idiome de l'optimiseur, puis de l'arithmétique optimisée en -O2 */
+    pub fn samples() -> Vec<(Vec<FlattenedInstruction>, Vec<u8>)> {
+        let idioms = vec![
+            Right(1),
+            Read,
+            Right(1),
+            Read,
+            Left(1),
+            MulAdd {
+                offset: 2,
+                factor: 3,
+            },
+            MulAdd {
+                offset: -1,
+                factor: 1,
+            },
+            Clear,
+            Right(1),
+            Loop(vec![Sub(1), Right(1), Sub(1), Left(1)]),
+            Right(1),
+            SetConst(300),
+            Add(250),
+            Loop(vec![Print, Left(1)]),
+            Right(1),
+            ScanRight(1),
+            Print,
+            Left(4),
+            Print,
+        ];
+        let arithmetic = vec![
+            Goto(0),
+            Builtin(Builtin::ReadNum, vec![0]),
+            Builtin(Builtin::ReadNum, vec![1]),
+            Builtin(Builtin::Mul, vec![0, 1, 2]),
+            Builtin(Builtin::PrintNum, vec![2]),
+            Builtin(Builtin::DivMod, vec![0, 1, 3, 4]),
+            Builtin(Builtin::PrintNum, vec![3]),
+            Builtin(Builtin::PrintNum, vec![4]),
+        ];
+        let arithmetic = expand_builtin(arithmetic, &Layout::default()).unwrap();
+        let (arithmetic, _) = transform_goto(arithmetic, Outputpointer::default());
+        let arithmetic = PassManager::new(OptLevel::O2).run(arithmetic.unwrap());
+        vec![(idioms, vec![7, 5]), (arithmetic, b"12 7\n".to_vec())]
+    }
+
+    /* sortie de l'interpréteur Brainfuck naïf, qui sert de référence */
+    pub fn expected(ist_list: &[FlattenedInstruction], input: &[u8]) -> Vec<u8> {
+        interpreter::run(&produce(ist_list.to_vec()).unwrap(), input)
+            .unwrap()
+            .output
+    }
+
+    /* vrai si l'outil peut être lancé, les tests d'exécution sont ignorés sinon */
+    pub fn available(tool: &str) -> bool {
+        let found = Command::new(tool)
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok();
+        if !found {
+            eprintln!("{} not found, skipping the execution test", tool);
+        }
+        found
+    }
+
+    /* dossier de travail propre à un test */
+    pub fn work_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bf_il_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /* fonction permettant de lancer une commande de compilation, qui doit réussir */
+    pub fn build(command: &mut Command) {
+        let output = command.output().unwrap();
+        assert!(
+            output.status.success(),
+            "{:?} failed:\n{}",
+            command,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    /* fonction permettant d'exécuter un programme compilé avec l'entrée donnée et de rendre sa sortie */
+    pub fn execute(program: &PathBuf, input: &[u8]) -> Vec<u8> {
+        let mut child = Command::new(program)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success(), "{:?} failed", program);
+        output.stdout
+    }
+}
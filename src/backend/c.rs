@@ -0,0 +1,110 @@
+use crate::backend::{push_line, TAPE_SIZE};
+use crate::function::FlattenedInstruction;
+
+/* fonction permettant de traduire une suite d'instructions en C, les boucles reconnues par l'optimiseur étant écrites directement */
+fn produce_block(ist_list: &[FlattenedInstruction], depth: usize, res: &mut String) {
+    for ist in ist_list {
+        match ist {
+            FlattenedInstruction::Noop | FlattenedInstruction::Sync(_) => (),
+            FlattenedInstruction::Print => push_line(res, depth, "putchar(*p);"),
+            FlattenedInstruction::Read => push_line(res, depth, "*p = input();"),
+            FlattenedInstruction::Add(n) => push_line(res, depth, &format!("*p += {};", n)),
+            FlattenedInstruction::Sub(n) => push_line(res, depth, &format!("*p -= {};", n)),
+            FlattenedInstruction::SetConst(v) => {
+                push_line(res, depth, &format!("*p = {};", v % 256))
+            }
+            FlattenedInstruction::Left(n) => push_line(res, depth, &format!("p -= {};", n)),
+            FlattenedInstruction::Right(n) => push_line(res, depth, &format!("p += {};", n)),
+            FlattenedInstruction::Clear => push_line(res, depth, "*p = 0;"),
+            FlattenedInstruction::MulAdd { offset, factor } => {
+                push_line(res, depth, &format!("p[{}] += *p * {};", offset, factor))
+            }
+            FlattenedInstruction::ScanLeft(stride) => {
+                push_line(res, depth, &format!("while (*p) p -= {};", stride))
+            }
+            FlattenedInstruction::ScanRight(stride) => {
+                push_line(res, depth, &format!("while (*p) p += {};", stride))
+            }
+            FlattenedInstruction::Loop(inner) => {
+                push_line(res, depth, "while (*p) {");
+                produce_block(inner, depth + 1, res);
+                push_line(res, depth, "}");
+            }
+            FlattenedInstruction::Annotation(context) => {
+                push_line(res, depth, &format!("/* {} */", context.describe()))
+            }
+            FlattenedInstruction::Goto(_) | FlattenedInstruction::Builtin(_, _) => unreachable!(),
+        }
+    }
+}
+
+/* fonction permettant de produire un programme C autonome: une bande de cellules de 8 bits, la fin de l'entrée donnant 0
+    la taille de la bande peut être changée à la compilation avec -DTAPE_SIZE=n
+*/
+pub fn produce_c(ist_list: &[FlattenedInstruction]) -> String {
+    let mut res = format!(
+        "#include <stdio.h>\n\
+         \n\
+         #ifndef TAPE_SIZE\n\
+         #define TAPE_SIZE {}\n\
+         #endif\n\
+         \n\
+         static unsigned char tape[TAPE_SIZE];\n\
+         \n\
+         static inline unsigned char input(void) {{\n\
+         \x20   int c = getchar();\n\
+         \x20   return c == EOF ? 0 : c;\n\
+         }}\n\
+         \n\
+         int main(void) {{\n\
+         \x20   unsigned char *p = tape;\n",
+        TAPE_SIZE
+    );
+    produce_block(ist_list, 1, &mut res);
+    push_line(&mut res, 1, "return 0;");
+    res.push_str("}\n");
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::testing::{available, build, execute, expected, samples, work_dir};
+    use std::process::Command;
+    use FlattenedInstruction::*;
+
+    #[test]
+    fn c_idioms() {
+        let code = produce_c(&[
+            Read,
+            MulAdd {
+                offset: 2,
+                factor: -3,
+            },
+            Clear,
+            Right(2),
+            Loop(vec![Print, ScanLeft(1)]),
+        ]);
+        assert!(code.ends_with(
+            "    unsigned char *p = tape;\n    *p = input();\n    p[2] += *p * -3;\n    *p = 0;\n    \
+             p += 2;\n    while (*p) {\n        putchar(*p);\n        while (*p) p -= 1;\n    }\n    \
+             return 0;\n}\n"
+        ));
+    }
+
+    #[test]
+    fn c_matches_brainfuck() {
+        if !available("cc") {
+            return;
+        }
+        let dir = work_dir("c");
+        for (i, (ist_list, input)) in samples().into_iter().enumerate() {
+            let source = dir.join(format!("prog{}.c", i));
+            let program = dir.join(format!("prog{}", i));
+            std::fs::write(&source, produce_c(&ist_list)).unwrap();
+            build(Command::new("cc").arg(&source).arg("-o").arg(&program));
+            assert_eq!(execute(&program, &input), expected(&ist_list, &input));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,198 @@
+use crate::backend::push_line;
+use crate::function::FlattenedInstruction;
+use crate::layout::VarType;
+
+/* options du module Rust produit: type des cellules et nombre de cellules de la bande */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RustOptions {
+    pub cell: VarType,
+    pub tape_size: usize,
+}
+
+impl RustOptions {
+    /* valeur écrite dans le source, ramenée dans l'intervalle du type des cellules */
+    fn literal(&self, value: i128) -> i128 {
+        value.rem_euclid(1 << (self.cell.width() * 8))
+    }
+
+    /* cellule située offset cases après la tête de lecture */
+    fn cell_at(offset: isize) -> String {
+        match offset {
+            0 => String::from("tape[p]"),
+            o if o > 0 => format!("tape[p + {}]", o),
+            o => format!("tape[p - {}]", o.unsigned_abs()),
+        }
+    }
+
+    /* fonction permettant de traduire une suite d'instructions en Rust, l'arithmétique des cellules étant modulaire comme en Brainfuck */
+    fn produce_block(&self, ist_list: &[FlattenedInstruction], depth: usize, res: &mut String) {
+        for ist in ist_list {
+            match ist {
+                FlattenedInstruction::Noop | FlattenedInstruction::Sync(_) => (),
+                FlattenedInstruction::Print => push_line(
+                    res,
+                    depth,
+                    match self.cell {
+                        VarType::U8 => "output.write_all(&[tape[p]]).expect(\"failed to write\");",
+                        _ => "output.write_all(&[tape[p] as u8]).expect(\"failed to write\");",
+                    },
+                ),
+                FlattenedInstruction::Read => push_line(res, depth, "tape[p] = read(input);"),
+                FlattenedInstruction::Add(n) => push_line(
+                    res,
+                    depth,
+                    &format!(
+                        "tape[p] = tape[p].wrapping_add({});",
+                        self.literal(*n as i128)
+                    ),
+                ),
+                FlattenedInstruction::Sub(n) => push_line(
+                    res,
+                    depth,
+                    &format!(
+                        "tape[p] = tape[p].wrapping_sub({});",
+                        self.literal(*n as i128)
+                    ),
+                ),
+                FlattenedInstruction::SetConst(v) => push_line(
+                    res,
+                    depth,
+                    &format!("tape[p] = {};", self.literal(*v as i128)),
+                ),
+                FlattenedInstruction::Left(n) => push_line(res, depth, &format!("p -= {};", n)),
+                FlattenedInstruction::Right(n) => push_line(res, depth, &format!("p += {};", n)),
+                FlattenedInstruction::Clear => push_line(res, depth, "tape[p] = 0;"),
+                FlattenedInstruction::MulAdd { offset, factor } => {
+                    let target = Self::cell_at(*offset);
+                    push_line(
+                        res,
+                        depth,
+                        &format!(
+                            "{} = {}.wrapping_add(tape[p].wrapping_mul({}));",
+                            target,
+                            target,
+                            self.literal(*factor as i128)
+                        ),
+                    )
+                }
+                FlattenedInstruction::ScanLeft(stride) => push_line(
+                    res,
+                    depth,
+                    &format!("while tape[p] != 0 {{ p -= {}; }}", stride),
+                ),
+                FlattenedInstruction::ScanRight(stride) => push_line(
+                    res,
+                    depth,
+                    &format!("while tape[p] != 0 {{ p += {}; }}", stride),
+                ),
+                FlattenedInstruction::Loop(inner) => {
+                    push_line(res, depth, "while tape[p] != 0 {");
+                    self.produce_block(inner, depth + 1, res);
+                    push_line(res, depth, "}");
+                }
+                FlattenedInstruction::Annotation(context) => {
+                    push_line(res, depth, &format!("// {}", context.describe()))
+                }
+                FlattenedInstruction::Goto(_) | FlattenedInstruction::Builtin(_, _) => {
+                    unreachable!()
+                }
+            }
+        }
+    }
+}
+
+/* fonction permettant de produire un module Rust exposant run(input, output), à inclure avec mod ou include!
+    la fin de l'entrée donne 0 et seul l'octet de poids faible d'une cellule est écrit
+*/
+pub fn produce_rust(ist_list: &[FlattenedInstruction], options: RustOptions) -> String {
+    let mut res = format!(
+        "use std::io::{{Read, Write}};\n\
+         \n\
+         pub type Cell = {};\n\
+         pub const TAPE_SIZE: usize = {};\n\
+         \n\
+         #[allow(unused)]\n\
+         fn read(input: &mut impl Read) -> Cell {{\n\
+         \x20   let mut byte = [0];\n\
+         \x20   match input.read(&mut byte) {{\n\
+         \x20       Ok(1) => Cell::from(byte[0]),\n\
+         \x20       _ => 0,\n\
+         \x20   }}\n\
+         }}\n\
+         \n\
+         #[allow(unused)]\n\
+         pub fn run(input: &mut impl Read, output: &mut impl Write) {{\n\
+         \x20   let mut tape: Vec<Cell> = vec![0; TAPE_SIZE];\n\
+         \x20   let mut p: usize = 0;\n",
+        options.cell.name(),
+        options.tape_size
+    );
+    options.produce_block(ist_list, 1, &mut res);
+    push_line(&mut res, 1, "output.flush().expect(\"failed to write\");");
+    res.push_str("}\n");
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::testing::{available, build, execute, expected, samples, work_dir};
+    use crate::backend::TAPE_SIZE;
+    use std::process::Command;
+    use FlattenedInstruction::*;
+
+    #[test]
+    fn rust_cells() {
+        let options = RustOptions {
+            cell: VarType::U16,
+            tape_size: 100,
+        };
+        let code = produce_rust(
+            &[
+                SetConst(70000),
+                MulAdd {
+                    offset: -1,
+                    factor: -2,
+                },
+                Clear,
+                Left(1),
+                Print,
+            ],
+            options,
+        );
+        assert!(code.contains("pub type Cell = u16;\npub const TAPE_SIZE: usize = 100;\n"));
+        assert!(code.ends_with(
+            "    tape[p] = 4464;\n    tape[p - 1] = tape[p - 1].wrapping_add(tape[p].wrapping_mul(65534));\n    \
+             tape[p] = 0;\n    p -= 1;\n    output.write_all(&[tape[p] as u8]).expect(\"failed to write\");\n    \
+             output.flush().expect(\"failed to write\");\n}\n"
+        ));
+    }
+
+    #[test]
+    fn rust_matches_brainfuck() {
+        if !available("rustc") {
+            return;
+        }
+        let options = RustOptions {
+            cell: VarType::U8,
+            tape_size: TAPE_SIZE,
+        };
+        let dir = work_dir("rust");
+        for (i, (ist_list, input)) in samples().into_iter().enumerate() {
+            let source = dir.join(format!("prog{}.rs", i));
+            let program = dir.join(format!("prog{}", i));
+            //le module produit est appelé depuis un main qui lui passe l'entrée et la sortie standard
+            std::fs::write(
+                &source,
+                format!(
+                    "mod bf {{\n{}}}\n\nfn main() {{\n    bf::run(&mut std::io::stdin(), &mut std::io::stdout());\n}}\n",
+                    produce_rust(&ist_list, options)
+                ),
+            )
+            .unwrap();
+            build(Command::new("rustc").arg(&source).arg("-o").arg(&program));
+            assert_eq!(execute(&program, &input), expected(&ist_list, &input));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+use crate::backend::push_line;
+use crate::function::FlattenedInstruction;
+
+/* taille d'une page de mémoire WebAssembly */
+const PAGE_SIZE: usize = 65536;
+
+/* adresse de la cellule située offset cases après la tête de lecture */
+fn address(offset: isize) -> String {
+    match offset {
+        0 => String::from("(local.get $p)"),
+        o => format!("(i32.add (local.get $p) (i32.const {}))", o),
+    }
+}
+
+/* instruction remplaçant la cellule courante par op appliqué à sa valeur et à n */
+fn update(op: &str, n: usize) -> String {
+    format!(
+        "(i32.store8 (local.get $p) ({} (i32.load8_u (local.get $p)) (i32.const {})))",
+        op,
+        n % 256
+    )
+}
+
+/* instruction déplaçant la tête de lecture */
+fn shift(op: &str, n: usize) -> String {
+    format!("(local.set $p ({} (local.get $p) (i32.const {})))", op, n)
+}
+
+/* fonction permettant de traduire une suite d'instructions en WebAssembly textuel
+    une boucle est un block contenant un loop: on sort du block quand la cellule vaut 0, sinon on recommence le loop
+*/
+fn produce_block(ist_list: &[FlattenedInstruction], depth: usize, res: &mut String) {
+    for ist in ist_list {
+        match ist {
+            FlattenedInstruction::Noop | FlattenedInstruction::Sync(_) => (),
+            FlattenedInstruction::Print => {
+                push_line(res, depth, "(call $write (i32.load8_u (local.get $p)))")
+            }
+            FlattenedInstruction::Read => {
+                push_line(res, depth, "(i32.store8 (local.get $p) (call $read))")
+            }
+            FlattenedInstruction::Add(n) => push_line(res, depth, &update("i32.add", *n)),
+            FlattenedInstruction::Sub(n) => push_line(res, depth, &update("i32.sub", *n)),
+            FlattenedInstruction::SetConst(v) => push_line(
+                res,
+                depth,
+                &format!("(i32.store8 (local.get $p) (i32.const {}))", v % 256),
+            ),
+            FlattenedInstruction::Left(n) => push_line(res, depth, &shift("i32.sub", *n)),
+            FlattenedInstruction::Right(n) => push_line(res, depth, &shift("i32.add", *n)),
+            FlattenedInstruction::Clear => {
+                push_line(res, depth, "(i32.store8 (local.get $p) (i32.const 0))")
+            }
+            FlattenedInstruction::MulAdd { offset, factor } => push_line(
+                res,
+                depth,
+                &format!(
+                    "(i32.store8 {} (i32.add (i32.load8_u {}) (i32.mul (i32.load8_u (local.get $p)) (i32.const {}))))",
+                    address(*offset),
+                    address(*offset),
+                    factor
+                ),
+            ),
+            FlattenedInstruction::ScanLeft(stride) => produce_block(
+                &[FlattenedInstruction::Loop(vec![FlattenedInstruction::Left(
+                    *stride,
+                )])],
+                depth,
+                res,
+            ),
+            FlattenedInstruction::ScanRight(stride) => produce_block(
+                &[FlattenedInstruction::Loop(vec![FlattenedInstruction::Right(
+                    *stride,
+                )])],
+                depth,
+                res,
+            ),
+            FlattenedInstruction::Loop(inner) => {
+                push_line(res, depth, "(block");
+                push_line(res, depth + 1, "(loop");
+                push_line(
+                    res,
+                    depth + 2,
+                    "(br_if 1 (i32.eqz (i32.load8_u (local.get $p))))",
+                );
+                produce_block(inner, depth + 2, res);
+                push_line(res, depth + 2, "(br 0)))");
+            }
+            FlattenedInstruction::Annotation(context) => {
+                push_line(res, depth, &format!(";; {}", context.describe()))
+            }
+            FlattenedInstruction::Goto(_) | FlattenedInstruction::Builtin(_, _) => unreachable!(),
+        }
+    }
+}
+
+/* fonction permettant de produire un module WebAssembly textuel: la bande est la mémoire linéaire, exportée sous le nom memory
+    l'hôte fournit env.read, qui rend l'octet lu ou 0 à la fin de l'entrée, et env.write qui reçoit l'octet à écrire
+    le programme s'exécute en appelant la fonction exportée run
+*/
+pub fn produce_wat(ist_list: &[FlattenedInstruction], tape_size: usize) -> String {
+    let mut res = String::from("(module\n");
+    push_line(
+        &mut res,
+        1,
+        "(import \"env\" \"read\" (func $read (result i32)))",
+    );
+    push_line(
+        &mut res,
+        1,
+        "(import \"env\" \"write\" (func $write (param i32)))",
+    );
+    push_line(
+        &mut res,
+        1,
+        &format!(
+            "(memory (export \"memory\") {})",
+            tape_size.div_ceil(PAGE_SIZE).max(1)
+        ),
+    );
+    push_line(&mut res, 1, "(func (export \"run\")");
+    push_line(&mut res, 2, "(local $p i32)");
+    produce_block(ist_list, 2, &mut res);
+    push_line(&mut res, 1, ")");
+    res.push_str(")\n");
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::testing::{expected, samples};
+    use crate::backend::TAPE_SIZE;
+    use wasmi::{Caller, Engine, Linker, Module, Store};
+
+    /* état de l'hôte: l'entrée restant à lire et la sortie produite */
+    struct Host {
+        input: Vec<u8>,
+        output: Vec<u8>,
+    }
+
+    fn execute(wat: &str, input: &[u8]) -> Vec<u8> {
+        let wasm = wat::parse_str(wat).unwrap();
+        let engine = Engine::default();
+        let module = Module::new(&engine, &wasm[..]).unwrap();
+        let mut input = input.to_vec();
+        input.reverse();
+        let mut store = Store::new(
+            &engine,
+            Host {
+                input,
+                output: Vec::new(),
+            },
+        );
+        let mut linker = <Linker<Host>>::new(&engine);
+        linker
+            .func_wrap("env", "read", |mut caller: Caller<'_, Host>| -> i32 {
+                caller.data_mut().input.pop().unwrap_or(0) as i32
+            })
+            .unwrap();
+        linker
+            .func_wrap("env", "write", |mut caller: Caller<'_, Host>, byte: i32| {
+                caller.data_mut().output.push(byte as u8)
+            })
+            .unwrap();
+        let instance = linker
+            .instantiate(&mut store, &module)
+            .unwrap()
+            .start(&mut store)
+            .unwrap();
+        instance
+            .get_typed_func::<(), ()>(&store, "run")
+            .unwrap()
+            .call(&mut store, ())
+            .unwrap();
+        store.into_data().output
+    }
+
+    #[test]
+    fn wat_matches_brainfuck() {
+        for (ist_list, input) in samples() {
+            assert_eq!(
+                execute(&produce_wat(&ist_list, TAPE_SIZE), &input),
+                expected(&ist_list, &input)
+            );
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use crate::backend::push_line;
+use crate::function::FlattenedInstruction;
+
+/* structure permettant de produire l'assembleur, la tête de lecture est dans %rbx et chaque boucle reçoit un numéro pour ses étiquettes */
+#[derive(Debug, Default)]
+struct Assembler {
+    code: String,
+    labels: usize,
+}
+
+impl Assembler {
+    fn push(&mut self, line: &str) {
+        push_line(&mut self.code, 1, line);
+    }
+
+    /* fonction permettant de produire une boucle dont le corps est produit par body, la condition étant testée à l'entrée et à la fin */
+    fn produce_loop(&mut self, body: impl FnOnce(&mut Self)) {
+        let label = self.labels;
+        self.labels += 1;
+        self.push("cmpb $0, (%rbx)");
+        self.push(&format!("je .Lend{}", label));
+        self.code.push_str(&format!(".Lstart{}:\n", label));
+        body(self);
+        self.push("cmpb $0, (%rbx)");
+        self.push(&format!("jne .Lstart{}", label));
+        self.code.push_str(&format!(".Lend{}:\n", label));
+    }
+
+    /* fonction permettant de traduire une suite d'instructions, une suite d'additions ou de déplacements fusionnée par l'optimiseur
+        donne une seule instruction add ou lea
+    */
+    fn produce_block(&mut self, ist_list: &[FlattenedInstruction]) {
+        for ist in ist_list {
+            match ist {
+                FlattenedInstruction::Noop | FlattenedInstruction::Sync(_) => (),
+                FlattenedInstruction::Print => self.push("call bf_write"),
+                FlattenedInstruction::Read => self.push("call bf_read"),
+                FlattenedInstruction::Add(n) => self.push(&format!("addb ${}, (%rbx)", n % 256)),
+                FlattenedInstruction::Sub(n) => self.push(&format!("subb ${}, (%rbx)", n % 256)),
+                FlattenedInstruction::SetConst(v) => {
+                    self.push(&format!("movb ${}, (%rbx)", v % 256))
+                }
+                FlattenedInstruction::Left(n) => self.push(&format!("lea -{}(%rbx), %rbx", n)),
+                FlattenedInstruction::Right(n) => self.push(&format!("lea {}(%rbx), %rbx", n)),
+                FlattenedInstruction::Clear => self.push("movb $0, (%rbx)"),
+                FlattenedInstruction::MulAdd { offset, factor } => {
+                    self.push("movzbl (%rbx), %eax");
+                    if *factor != 1 {
+                        self.push(&format!("imul ${}, %eax, %eax", factor));
+                    }
+                    self.push(&format!("addb %al, {}(%rbx)", offset));
+                }
+                FlattenedInstruction::ScanLeft(stride) => {
+                    self.produce_loop(|asm| asm.push(&format!("lea -{}(%rbx), %rbx", stride)))
+                }
+                FlattenedInstruction::ScanRight(stride) => {
+                    self.produce_loop(|asm| asm.push(&format!("lea {}(%rbx), %rbx", stride)))
+                }
+                FlattenedInstruction::Loop(inner) => {
+                    self.produce_loop(|asm| asm.produce_block(inner))
+                }
+                FlattenedInstruction::Annotation(context) => {
+                    self.push(&format!("# {}", context.describe()))
+                }
+                FlattenedInstruction::Goto(_) | FlattenedInstruction::Builtin(_, _) => {
+                    unreachable!()
+                }
+            }
+        }
+    }
+}
+
+/* fonction permettant de produire un programme autonome pour Linux en syntaxe GNU as: la bande est dans .bss
+    les entrées-sorties se font octet par octet avec les appels système read et write, la fin de l'entrée donne 0
+    il s'assemble avec: as prog.s -o prog.o && ld prog.o -o prog
+*/
+pub fn produce_x86_64(ist_list: &[FlattenedInstruction], tape_size: usize) -> String {
+    let mut asm = Assembler::default();
+    asm.code.push_str(&format!(
+        "    .bss\n\
+         tape:\n\
+         \x20   .zero {}\n\
+         \n\
+         \x20   .text\n\
+         \x20   .globl _start\n\
+         _start:\n",
+        tape_size
+    ));
+    asm.push("lea tape(%rip), %rbx");
+    asm.produce_block(ist_list);
+    asm.push("mov $60, %eax");
+    asm.push("xor %edi, %edi");
+    asm.push("syscall");
+    asm.code.push_str(
+        "\n\
+         bf_write:\n\
+         \x20   mov $1, %eax\n\
+         \x20   mov $1, %edi\n\
+         \x20   mov %rbx, %rsi\n\
+         \x20   mov $1, %edx\n\
+         \x20   syscall\n\
+         \x20   ret\n\
+         \n\
+         bf_read:\n\
+         \x20   movb $0, (%rbx)\n\
+         \x20   xor %eax, %eax\n\
+         \x20   xor %edi, %edi\n\
+         \x20   mov %rbx, %rsi\n\
+         \x20   mov $1, %edx\n\
+         \x20   syscall\n\
+         \x20   ret\n",
+    );
+    asm.code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::testing::{available, build, execute, expected, samples, work_dir};
+    use crate::backend::TAPE_SIZE;
+    use std::process::Command;
+    use FlattenedInstruction::*;
+
+    #[test]
+    fn x86_64_loops() {
+        let code = produce_x86_64(
+            &[
+                Add(3),
+                Loop(vec![
+                    MulAdd {
+                        offset: 1,
+                        factor: 2,
+                    },
+                    Clear,
+                    Right(1),
+                ]),
+                Left(1),
+            ],
+            100,
+        );
+        assert!(code.contains(".zero 100\n"));
+        assert!(code.contains(
+            "    addb $3, (%rbx)\n    cmpb $0, (%rbx)\n    je .Lend0\n.Lstart0:\n    movzbl (%rbx), %eax\n    \
+             imul $2, %eax, %eax\n    addb %al, 1(%rbx)\n    movb $0, (%rbx)\n    lea 1(%rbx), %rbx\n    \
+             cmpb $0, (%rbx)\n    jne .Lstart0\n.Lend0:\n    lea -1(%rbx), %rbx\n"
+        ));
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+    #[test]
+    fn x86_64_matches_brainfuck() {
+        if !available("as") || !available("ld") {
+            return;
+        }
+        let dir = work_dir("x86_64");
+        for (i, (ist_list, input)) in samples().into_iter().enumerate() {
+            let source = dir.join(format!("prog{}.s", i));
+            let object = dir.join(format!("prog{}.o", i));
+            let program = dir.join(format!("prog{}", i));
+            std::fs::write(&source, produce_x86_64(&ist_list, TAPE_SIZE)).unwrap();
+            build(Command::new("as").arg(&source).arg("-o").arg(&object));
+            build(Command::new("ld").arg(&object).arg("-o").arg(&program));
+            assert_eq!(execute(&program, &input), expected(&ist_list, &input));
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
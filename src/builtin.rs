@@ -0,0 +1,1315 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::function::FlattenedInstruction;
+use crate::ir::step;
+use crate::layout::{Layout, VarType};
+
+/* fonctions prédéfinies par le compilateur, leurs arguments sont des cellules de la bande
+    et leurs expansions utilisent des cellules de travail allouées par le compilateur
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Builtin {
+    Add,
+    Sub,
+    SetConst,
+    PrintNum,
+    ReadNum,
+    Mul,
+    DivMod,
+    Eq,
+    Lt,
+    Not,
+    Load,
+    Store,
+    Push,
+    Pop,
+    IsEmpty,
+}
+
+impl Builtin {
+    pub const ALL: [Self; 15] = [
+        Self::Add,
+        Self::Sub,
+        Self::SetConst,
+        Self::PrintNum,
+        Self::ReadNum,
+        Self::Mul,
+        Self::DivMod,
+        Self::Eq,
+        Self::Lt,
+        Self::Not,
+        Self::Load,
+        Self::Store,
+        Self::Push,
+        Self::Pop,
+        Self::IsEmpty,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "printnum" => Some(Self::PrintNum),
+            "readnum" => Some(Self::ReadNum),
+            "mul" => Some(Self::Mul),
+            "divmod" => Some(Self::DivMod),
+            "eq" => Some(Self::Eq),
+            "lt" => Some(Self::Lt),
+            "not" => Some(Self::Not),
+            "load" => Some(Self::Load),
+            "store" => Some(Self::Store),
+            "push" => Some(Self::Push),
+            "pop" => Some(Self::Pop),
+            "isempty" => Some(Self::IsEmpty),
+            _ => None,
+        }
+    }
+
+    /* nom de la fonction en minuscules, tel qu'écrit dans le source pour celles que l'on peut appeler */
+    #[inline]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::SetConst => "setconst",
+            Self::PrintNum => "printnum",
+            Self::ReadNum => "readnum",
+            Self::Mul => "mul",
+            Self::DivMod => "divmod",
+            Self::Eq => "eq",
+            Self::Lt => "lt",
+            Self::Not => "not",
+            Self::Load => "load",
+            Self::Store => "store",
+            Self::Push => "push",
+            Self::Pop => "pop",
+            Self::IsEmpty => "isempty",
+        }
+    }
+
+    #[inline]
+    pub const fn arity(&self) -> usize {
+        match self {
+            Self::PrintNum | Self::ReadNum => 1,
+            Self::Not | Self::Add | Self::Sub | Self::SetConst => 2,
+            Self::Push | Self::Pop | Self::IsEmpty => 2,
+            Self::Mul | Self::Eq | Self::Lt | Self::Load | Self::Store => 3,
+            Self::DivMod => 4,
+        }
+    }
+
+    /* arguments désignant des cellules, les autres sont des constantes */
+    pub fn cell_args<'a, T>(&self, args: &'a [T]) -> &'a [T] {
+        match self {
+            Self::Add | Self::Sub | Self::SetConst => &args[..1],
+            Self::Push | Self::Pop | Self::IsEmpty => &args[1..],
+            _ => args,
+        }
+    }
+
+    /* built-ins acceptant des variables sur plusieurs cellules */
+    #[inline]
+    pub const fn supports_wide(&self) -> bool {
+        matches!(
+            self,
+            Self::Add | Self::Sub | Self::SetConst | Self::PrintNum | Self::Eq | Self::Lt
+        )
+    }
+}
+
+/* structure permettant d'allouer les cellules de travail, situées après la dernière cellule utilisée par le programme
+    chaque expansion rend ses cellules à zéro avant de les libérer, les piles commencent après toutes les cellules de travail
+*/
+struct Scratch {
+    base: usize,
+    used: usize,
+    peak: usize,
+    stacks: usize,
+}
+
+impl Scratch {
+    fn alloc(&mut self) -> usize {
+        self.used += 1;
+        self.peak = self.peak.max(self.used);
+        self.base + self.used - 1
+    }
+}
+
+/* fonction permettant de trouver la plus grande cellule adressée par le programme
+    en suivant la tête de lecture tant que sa position est connue, les déplacements relatifs pouvant dépasser le dernier goto
+*/
+fn max_cell(ist_list: &[FlattenedInstruction]) -> usize {
+    fn visit(ist_list: &[FlattenedInstruction], mut position: Option<isize>, max: &mut isize) {
+        for ist in ist_list {
+            match ist {
+                FlattenedInstruction::Builtin(builtin, args) => {
+                    let cell = builtin.cell_args(args).iter().copied().max().unwrap_or(0);
+                    *max = (*max).max(cell as isize);
+                }
+                FlattenedInstruction::Loop(inner) => visit(inner, position, max),
+                _ => (),
+            }
+            position = step(ist, position);
+            *max = (*max).max(position.unwrap_or(0));
+        }
+    }
+    let mut max = 0;
+    visit(ist_list, Some(0), &mut max);
+    max as usize
+}
+
+fn goto_add(out: &mut Vec<FlattenedInstruction>, cell: usize, val: usize) {
+    out.push(FlattenedInstruction::Goto(cell));
+    out.push(FlattenedInstruction::Add(val));
+}
+
+fn goto_sub(out: &mut Vec<FlattenedInstruction>, cell: usize, val: usize) {
+    out.push(FlattenedInstruction::Goto(cell));
+    out.push(FlattenedInstruction::Sub(val));
+}
+
+/* [-] : remet à zéro la cellule courante */
+fn clear_current() -> FlattenedInstruction {
+    FlattenedInstruction::Loop(vec![FlattenedInstruction::Sub(1)])
+}
+
+fn clear(out: &mut Vec<FlattenedInstruction>, cell: usize) {
+    out.push(FlattenedInstruction::Goto(cell));
+    out.push(clear_current());
+}
+
+/* boucle tant que la cellule est non nulle, le corps revient toujours sur la cellule testée afin que transform_goto garde une position prédictible */
+fn repeat(out: &mut Vec<FlattenedInstruction>, cell: usize, mut body: Vec<FlattenedInstruction>) {
+    body.push(FlattenedInstruction::Goto(cell));
+    out.push(FlattenedInstruction::Goto(cell));
+    out.push(FlattenedInstruction::Loop(body));
+}
+
+/* vide src en ajoutant sa valeur à chacune des destinations */
+fn transfer(out: &mut Vec<FlattenedInstruction>, src: usize, dsts: &[usize]) {
+    let mut body = vec![FlattenedInstruction::Sub(1)];
+    dsts.iter().for_each(|dst| goto_add(&mut body, *dst, 1));
+    repeat(out, src, body);
+}
+
+/* ajoute src à dst sans détruire src */
+fn copy(out: &mut Vec<FlattenedInstruction>, src: usize, dst: usize, scratch: &mut Scratch) {
+    let mark = scratch.used;
+    let tmp = scratch.alloc();
+    transfer(out, src, &[dst, tmp]);
+    transfer(out, tmp, &[src]);
+    scratch.used = mark;
+}
+
+/* res (nulle en entrée) vaut 1 si a == b, 0 sinon */
+fn equal(
+    out: &mut Vec<FlattenedInstruction>,
+    a: usize,
+    b: usize,
+    res: usize,
+    scratch: &mut Scratch,
+) {
+    let mark = scratch.used;
+    let diff = scratch.alloc();
+    let count = scratch.alloc();
+    copy(out, a, diff, scratch);
+    copy(out, b, count, scratch);
+
+    let mut body = vec![FlattenedInstruction::Sub(1)];
+    goto_sub(&mut body, diff, 1);
+    repeat(out, count, body);
+
+    goto_add(out, res, 1);
+    let mut body = vec![clear_current()];
+    goto_sub(&mut body, res, 1);
+    repeat(out, diff, body);
+    scratch.used = mark;
+}
+
+/* res (nulle en entrée) vaut 1 si a est nulle, 0 sinon */
+fn not(out: &mut Vec<FlattenedInstruction>, a: usize, res: usize, scratch: &mut Scratch) {
+    let mark = scratch.used;
+    let tmp = scratch.alloc();
+    goto_add(out, res, 1);
+    copy(out, a, tmp, scratch);
+    let mut body = vec![clear_current()];
+    goto_sub(&mut body, res, 1);
+    repeat(out, tmp, body);
+    scratch.used = mark;
+}
+
+/* res (nulle en entrée) vaut 1 si a < b (non signé), 0 sinon */
+fn less_than(
+    out: &mut Vec<FlattenedInstruction>,
+    a: usize,
+    b: usize,
+    res: usize,
+    scratch: &mut Scratch,
+) {
+    let mark = scratch.used;
+    let left = scratch.alloc();
+    let count = scratch.alloc();
+    let is_zero = scratch.alloc();
+    let tmp = scratch.alloc();
+    copy(out, a, left, scratch);
+    copy(out, b, count, scratch);
+
+    /* on décrémente les deux copies ensemble, si la copie de a atteint zéro avant celle de b alors a < b */
+    let mut body = vec![FlattenedInstruction::Sub(1)];
+    goto_add(&mut body, is_zero, 1);
+    copy(&mut body, left, tmp, scratch);
+    let mut not_zero = vec![clear_current()];
+    goto_sub(&mut not_zero, is_zero, 1);
+    repeat(&mut body, tmp, not_zero);
+
+    let mut found = vec![FlattenedInstruction::Sub(1)];
+    goto_add(&mut found, res, 1);
+    clear(&mut found, count);
+    repeat(&mut body, is_zero, found);
+
+    goto_sub(&mut body, left, 1);
+    repeat(out, count, body);
+
+    clear(out, left);
+    scratch.used = mark;
+}
+
+/* q et r (nulles en entrée) reçoivent le quotient et le reste de n par d, une division par zéro donne q = 0 et r = n */
+fn divmod(
+    out: &mut Vec<FlattenedInstruction>,
+    n: usize,
+    d: usize,
+    q: usize,
+    r: usize,
+    scratch: &mut Scratch,
+) {
+    let mark = scratch.used;
+    let count = scratch.alloc();
+    let reached = scratch.alloc();
+    copy(out, n, count, scratch);
+
+    let mut body = vec![FlattenedInstruction::Sub(1)];
+    goto_add(&mut body, r, 1);
+    equal(&mut body, r, d, reached, scratch);
+    let mut wrap = vec![FlattenedInstruction::Sub(1)];
+    clear(&mut wrap, r);
+    goto_add(&mut wrap, q, 1);
+    repeat(&mut body, reached, wrap);
+    repeat(out, count, body);
+    scratch.used = mark;
+}
+
+/* res (nulle en entrée) reçoit a * b modulo la taille d'une cellule */
+fn mul(out: &mut Vec<FlattenedInstruction>, a: usize, b: usize, res: usize, scratch: &mut Scratch) {
+    let mark = scratch.used;
+    let count = scratch.alloc();
+    copy(out, b, count, scratch);
+
+    let mut body = vec![FlattenedInstruction::Sub(1)];
+    copy(&mut body, a, res, scratch);
+    repeat(out, count, body);
+    scratch.used = mark;
+}
+
+/* affiche le chiffre contenu dans la cellule sans la modifier */
+fn print_digit(out: &mut Vec<FlattenedInstruction>, cell: usize) {
+    goto_add(out, cell, b'0' as usize);
+    out.push(FlattenedInstruction::Print);
+    out.push(FlattenedInstruction::Sub(b'0' as usize));
+}
+
+/* affiche en décimal la valeur de la cellule, sans zéro non significatif */
+fn print_num(out: &mut Vec<FlattenedInstruction>, cell: usize, scratch: &mut Scratch) {
+    let mark = scratch.used;
+    let ten = scratch.alloc();
+    let tens = scratch.alloc();
+    let units = scratch.alloc();
+    let hundreds = scratch.alloc();
+    let middle = scratch.alloc();
+    let flag = scratch.alloc();
+
+    goto_add(out, ten, 10);
+    divmod(out, cell, ten, tens, units, scratch);
+    divmod(out, tens, ten, hundreds, middle, scratch);
+
+    copy(out, hundreds, flag, scratch);
+    let mut body = vec![clear_current()];
+    print_digit(&mut body, hundreds);
+    repeat(out, flag, body);
+
+    copy(out, hundreds, flag, scratch);
+    copy(out, middle, flag, scratch);
+    let mut body = vec![clear_current()];
+    print_digit(&mut body, middle);
+    repeat(out, flag, body);
+
+    print_digit(out, units);
+
+    [ten, tens, units, hundreds, middle]
+        .into_iter()
+        .for_each(|tmp| clear(out, tmp));
+    scratch.used = mark;
+}
+
+/* lit un nombre décimal sur l'entrée jusqu'au premier caractère qui n'est pas un chiffre (ou la fin de l'entrée) */
+fn read_num(out: &mut Vec<FlattenedInstruction>, cell: usize, scratch: &mut Scratch) {
+    let mark = scratch.used;
+    let acc = scratch.alloc();
+    let running = scratch.alloc();
+    let ten = scratch.alloc();
+    let digit = scratch.alloc();
+    let is_digit = scratch.alloc();
+    let tmp = scratch.alloc();
+
+    goto_add(out, ten, 10);
+    goto_add(out, running, 1);
+
+    let mut body = vec![FlattenedInstruction::Sub(1)];
+    body.push(FlattenedInstruction::Goto(digit));
+    body.push(FlattenedInstruction::Read);
+    body.push(FlattenedInstruction::Sub(b'0' as usize));
+    less_than(&mut body, digit, ten, is_digit, scratch);
+
+    let mut accumulate = vec![FlattenedInstruction::Sub(1)];
+    mul(&mut accumulate, acc, ten, tmp, scratch);
+    clear(&mut accumulate, acc);
+    transfer(&mut accumulate, tmp, &[acc]);
+    transfer(&mut accumulate, digit, &[acc]);
+    goto_add(&mut accumulate, running, 1);
+    repeat(&mut body, is_digit, accumulate);
+
+    clear(&mut body, digit);
+    repeat(out, running, body);
+
+    clear(out, ten);
+    clear(out, cell);
+    transfer(out, acc, &[cell]);
+    scratch.used = mark;
+}
+
+/* octet de rang i d'une variable de largeur width, une cellule nulle remplace les octets manquants */
+fn byte(cell: usize, width: usize, i: usize, zero: usize) -> usize {
+    if i < width {
+        cell + i
+    } else {
+        zero
+    }
+}
+
+/* incrémente l'entier formé par les cellules en propageant la retenue */
+fn increment(out: &mut Vec<FlattenedInstruction>, cells: &[usize], scratch: &mut Scratch) {
+    goto_add(out, cells[0], 1);
+    if cells.len() > 1 {
+        let mark = scratch.used;
+        let carry = scratch.alloc();
+        not(out, cells[0], carry, scratch);
+        let mut body = vec![FlattenedInstruction::Sub(1)];
+        increment(&mut body, &cells[1..], scratch);
+        repeat(out, carry, body);
+        scratch.used = mark;
+    }
+}
+
+/* décrémente l'entier formé par les cellules en propageant l'emprunt */
+fn decrement(out: &mut Vec<FlattenedInstruction>, cells: &[usize], scratch: &mut Scratch) {
+    if cells.len() > 1 {
+        let mark = scratch.used;
+        let borrow = scratch.alloc();
+        not(out, cells[0], borrow, scratch);
+        goto_sub(out, cells[0], 1);
+        let mut body = vec![FlattenedInstruction::Sub(1)];
+        decrement(&mut body, &cells[1..], scratch);
+        repeat(out, borrow, body);
+        scratch.used = mark;
+    } else {
+        goto_sub(out, cells[0], 1);
+    }
+}
+
+/* ajoute (ou retire) une constante à une variable sur plusieurs cellules, octet par octet en propageant la retenue */
+fn add_const(
+    out: &mut Vec<FlattenedInstruction>,
+    cell: usize,
+    width: usize,
+    value: usize,
+    subtract: bool,
+    scratch: &mut Scratch,
+) {
+    let cells = (cell..cell + width).collect::<Vec<_>>();
+    for i in 0..width {
+        let part = (value >> (8 * i)) & 0xff;
+        if part == 0 {
+            continue;
+        }
+        if i + 1 == width {
+            out.push(FlattenedInstruction::Goto(cells[i]));
+            out.push(match subtract {
+                true => FlattenedInstruction::Sub(part),
+                false => FlattenedInstruction::Add(part),
+            });
+            continue;
+        }
+
+        let mark = scratch.used;
+        let constant = scratch.alloc();
+        let carry = scratch.alloc();
+        goto_add(out, constant, part);
+        if subtract {
+            /* il y a emprunt si l'octet est plus petit que la constante avant la soustraction */
+            less_than(out, cells[i], constant, carry, scratch);
+            goto_sub(out, cells[i], part);
+        } else {
+            /* il y a retenue si l'octet est plus petit que la constante après l'addition */
+            goto_add(out, cells[i], part);
+            less_than(out, cells[i], constant, carry, scratch);
+        }
+        clear(out, constant);
+
+        let mut body = vec![FlattenedInstruction::Sub(1)];
+        match subtract {
+            true => decrement(&mut body, &cells[i + 1..], scratch),
+            false => increment(&mut body, &cells[i + 1..], scratch),
+        }
+        repeat(out, carry, body);
+        scratch.used = mark;
+    }
+}
+
+fn set_const(out: &mut Vec<FlattenedInstruction>, cell: usize, width: usize, value: usize) {
+    for i in 0..width {
+        out.push(FlattenedInstruction::Goto(cell + i));
+        out.push(FlattenedInstruction::SetConst((value >> (8 * i)) & 0xff));
+    }
+}
+
+/* res (nulle en entrée) vaut 1 si les deux entiers sur plusieurs cellules sont égaux */
+fn equal_wide(
+    out: &mut Vec<FlattenedInstruction>,
+    (a, a_width): (usize, usize),
+    (b, b_width): (usize, usize),
+    res: usize,
+    scratch: &mut Scratch,
+) {
+    let mark = scratch.used;
+    let zero = scratch.alloc();
+    let same = scratch.alloc();
+    let differ = scratch.alloc();
+    goto_add(out, res, 1);
+    for i in 0..a_width.max(b_width) {
+        equal(
+            out,
+            byte(a, a_width, i, zero),
+            byte(b, b_width, i, zero),
+            same,
+            scratch,
+        );
+        not(out, same, differ, scratch);
+        clear(out, same);
+        let mut body = vec![FlattenedInstruction::Sub(1)];
+        clear(&mut body, res);
+        repeat(out, differ, body);
+    }
+    scratch.used = mark;
+}
+
+/* res (nulle en entrée) vaut 1 si a < b, en comparant les octets du poids fort au poids faible */
+fn less_than_wide(
+    out: &mut Vec<FlattenedInstruction>,
+    (a, a_width): (usize, usize),
+    (b, b_width): (usize, usize),
+    res: usize,
+    scratch: &mut Scratch,
+) {
+    let mark = scratch.used;
+    let zero = scratch.alloc();
+    let undecided = scratch.alloc();
+    let flag = scratch.alloc();
+    let lower = scratch.alloc();
+    let same = scratch.alloc();
+    let differ = scratch.alloc();
+    goto_add(out, undecided, 1);
+    for i in (0..a_width.max(b_width)).rev() {
+        let (a_byte, b_byte) = (byte(a, a_width, i, zero), byte(b, b_width, i, zero));
+        copy(out, undecided, flag, scratch);
+
+        let mut body = vec![FlattenedInstruction::Sub(1)];
+        less_than(&mut body, a_byte, b_byte, lower, scratch);
+        let mut found = vec![FlattenedInstruction::Sub(1)];
+        goto_add(&mut found, res, 1);
+        repeat(&mut body, lower, found);
+
+        equal(&mut body, a_byte, b_byte, same, scratch);
+        not(&mut body, same, differ, scratch);
+        clear(&mut body, same);
+        let mut decided = vec![FlattenedInstruction::Sub(1)];
+        clear(&mut decided, undecided);
+        repeat(&mut body, differ, decided);
+
+        repeat(out, flag, body);
+    }
+    clear(out, undecided);
+    scratch.used = mark;
+}
+
+/* divise sur place par 10 l'entier formé par les cellules (octet de poids faible en premier), rem (nulle en entrée) reçoit le reste
+    pour chaque octet : (r * 256 + x) / 10 = r * 25 + x / 10 + (r * 6 + x % 10) / 10
+*/
+fn div10(out: &mut Vec<FlattenedInstruction>, cells: &[usize], rem: usize, scratch: &mut Scratch) {
+    let mark = scratch.used;
+    let ten = scratch.alloc();
+    let six = scratch.alloc();
+    let quarter = scratch.alloc();
+    let high = scratch.alloc();
+    let low = scratch.alloc();
+    let partial = scratch.alloc();
+    let carry = scratch.alloc();
+    let digit = scratch.alloc();
+    goto_add(out, ten, 10);
+    goto_add(out, six, 6);
+    goto_add(out, quarter, 25);
+
+    for cell in cells.iter().rev() {
+        divmod(out, *cell, ten, high, low, scratch);
+        mul(out, rem, six, partial, scratch);
+        transfer(out, low, &[partial]);
+        divmod(out, partial, ten, carry, digit, scratch);
+        clear(out, partial);
+
+        clear(out, *cell);
+        mul(out, rem, quarter, *cell, scratch);
+        transfer(out, high, &[*cell]);
+        transfer(out, carry, &[*cell]);
+        clear(out, rem);
+        transfer(out, digit, &[rem]);
+    }
+
+    [ten, six, quarter]
+        .into_iter()
+        .for_each(|tmp| clear(out, tmp));
+    scratch.used = mark;
+}
+
+/* affiche en décimal un entier sur plusieurs cellules, sans zéro non significatif */
+fn print_num_wide(
+    out: &mut Vec<FlattenedInstruction>,
+    cell: usize,
+    width: usize,
+    scratch: &mut Scratch,
+) {
+    let mark = scratch.used;
+    let work = (0..width).map(|_| scratch.alloc()).collect::<Vec<_>>();
+    let digit_count = ((1u64 << (8 * width)) - 1).to_string().len();
+    let digits = (0..digit_count)
+        .map(|_| scratch.alloc())
+        .collect::<Vec<_>>();
+    let started = scratch.alloc();
+    let flag = scratch.alloc();
+
+    (0..width).for_each(|i| copy(out, cell + i, work[i], scratch));
+    digits
+        .iter()
+        .for_each(|digit| div10(out, &work, *digit, scratch));
+
+    /* les chiffres sont produits du poids faible au poids fort */
+    for (i, digit) in digits.iter().enumerate().rev() {
+        if i > 0 {
+            copy(out, *digit, flag, scratch);
+            let mut body = vec![clear_current()];
+            clear(&mut body, started);
+            goto_add(&mut body, started, 1);
+            repeat(out, flag, body);
+
+            copy(out, started, flag, scratch);
+            let mut body = vec![FlattenedInstruction::Sub(1)];
+            print_digit(&mut body, *digit);
+            repeat(out, flag, body);
+        } else {
+            print_digit(out, *digit);
+        }
+        clear(out, *digit);
+    }
+
+    clear(out, started);
+    work.into_iter().for_each(|tmp| clear(out, tmp));
+    scratch.used = mark;
+}
+
+/* [->>>+<<<] : déplace la valeur de la cellule courante de offset cellules, la tête revient sur la cellule courante */
+fn shift(offset: isize) -> FlattenedInstruction {
+    let distance = offset.unsigned_abs();
+    let (there, back) = if offset > 0 {
+        (
+            FlattenedInstruction::Right(distance),
+            FlattenedInstruction::Left(distance),
+        )
+    } else {
+        (
+            FlattenedInstruction::Left(distance),
+            FlattenedInstruction::Right(distance),
+        )
+    };
+    FlattenedInstruction::Loop(vec![
+        FlattenedInstruction::Sub(1),
+        there,
+        FlattenedInstruction::Add(1),
+        back,
+    ])
+}
+
+/* un tableau est une suite d'emplacements de trois cellules (index, valeur transportée, élément), précédée d'un emplacement de garde
+    le curseur part du premier emplacement avec l'index, avance d'un emplacement en le décrémentant et laisse un 1 derrière lui,
+    le retour s'arrête sur la cellule d'index de la garde qui reste toujours nulle
+*/
+fn walk_to_index(out: &mut Vec<FlattenedInstruction>, carry_value: bool) {
+    let mut body = vec![FlattenedInstruction::Sub(1), shift(3)];
+    if carry_value {
+        body.extend([
+            FlattenedInstruction::Right(1),
+            shift(3),
+            FlattenedInstruction::Left(1),
+        ]);
+    }
+    body.extend([FlattenedInstruction::Add(1), FlattenedInstruction::Right(3)]);
+    out.push(FlattenedInstruction::Loop(body));
+}
+
+/* écrit src dans l'élément d'index idx du tableau commençant en base */
+fn store(
+    out: &mut Vec<FlattenedInstruction>,
+    base: usize,
+    idx: usize,
+    src: usize,
+    scratch: &mut Scratch,
+) {
+    let slot = base + 3;
+    copy(out, idx, slot, scratch);
+    copy(out, src, slot + 1, scratch);
+    out.push(FlattenedInstruction::Goto(slot));
+    walk_to_index(out, true);
+
+    out.extend([
+        FlattenedInstruction::Right(2),
+        clear_current(),
+        FlattenedInstruction::Left(1),
+        shift(1),
+        FlattenedInstruction::Left(4),
+        FlattenedInstruction::Loop(vec![
+            FlattenedInstruction::Sub(1),
+            FlattenedInstruction::Left(3),
+        ]),
+        FlattenedInstruction::Sync(base),
+    ]);
+}
+
+/* copie dans dst l'élément d'index idx du tableau commençant en base, la valeur revient dans la garde */
+fn load(
+    out: &mut Vec<FlattenedInstruction>,
+    base: usize,
+    idx: usize,
+    dst: usize,
+    scratch: &mut Scratch,
+) {
+    let slot = base + 3;
+    copy(out, idx, slot, scratch);
+    out.push(FlattenedInstruction::Goto(slot));
+    walk_to_index(out, false);
+
+    let carry_back = [
+        FlattenedInstruction::Right(1),
+        shift(-3),
+        FlattenedInstruction::Left(4),
+    ];
+    let mut back = vec![FlattenedInstruction::Sub(1)];
+    back.extend(carry_back.clone());
+    out.extend([
+        FlattenedInstruction::Right(2),
+        FlattenedInstruction::Loop(vec![
+            FlattenedInstruction::Sub(1),
+            FlattenedInstruction::Left(1),
+            FlattenedInstruction::Add(1),
+            FlattenedInstruction::Left(1),
+            FlattenedInstruction::Add(1),
+            FlattenedInstruction::Right(2),
+        ]),
+        FlattenedInstruction::Left(2),
+        shift(2),
+    ]);
+    out.extend(carry_back);
+    out.push(FlattenedInstruction::Loop(back));
+    out.push(FlattenedInstruction::Sync(base));
+
+    clear(out, dst);
+    transfer(out, base + 1, &[dst]);
+}
+
+/* exécute body seulement si l'index idx est inférieur à la longueur len du tableau, sinon exécute outside
+    sans cette garde un index trop grand ferait sortir le parcours du tableau et écraser les variables qui le suivent
+    un index tenant dans une cellule est toujours valide pour un tableau d'au moins 256 éléments
+*/
+fn in_bounds(
+    out: &mut Vec<FlattenedInstruction>,
+    idx: usize,
+    len: usize,
+    scratch: &mut Scratch,
+    body: impl FnOnce(&mut Vec<FlattenedInstruction>, &mut Scratch),
+    mut outside: Vec<FlattenedInstruction>,
+) {
+    if len > 255 {
+        return body(out, scratch);
+    }
+    let mark = scratch.used;
+    let inside = scratch.alloc();
+    let bound = scratch.alloc();
+    goto_add(out, bound, len);
+    less_than(out, idx, bound, inside, scratch);
+    clear(out, bound);
+    if !outside.is_empty() {
+        let not_inside = scratch.alloc();
+        not(out, inside, not_inside, scratch);
+        clear(&mut outside, not_inside);
+        repeat(out, not_inside, outside);
+    }
+    let mut then = Vec::new();
+    body(&mut then, scratch);
+    clear(&mut then, inside);
+    repeat(out, inside, then);
+    scratch.used = mark;
+}
+
+/* chaque pile est une suite d'emplacements de trois cellules (drapeau, élément, valeur transportée), le premier est une garde au drapeau nul
+    les k piles sont entrelacées, on passe d'un emplacement au suivant en avançant de 3k cellules
+*/
+fn push(
+    out: &mut Vec<FlattenedInstruction>,
+    base: usize,
+    stride: usize,
+    src: usize,
+    scratch: &mut Scratch,
+) {
+    copy(out, src, base + 2, scratch);
+    out.extend([
+        FlattenedInstruction::Goto(base + 2),
+        shift(stride as isize),
+        FlattenedInstruction::Right(stride - 2),
+        FlattenedInstruction::Loop(vec![
+            FlattenedInstruction::Right(2),
+            shift(stride as isize),
+            FlattenedInstruction::Right(stride - 2),
+        ]),
+        FlattenedInstruction::Add(1),
+        FlattenedInstruction::Right(2),
+        shift(-1),
+        FlattenedInstruction::Left(2),
+        FlattenedInstruction::Loop(vec![FlattenedInstruction::Left(stride)]),
+        FlattenedInstruction::Sync(base),
+    ]);
+}
+
+/* retire le sommet de la pile dans dst, une pile vide donne 0 */
+fn pop(out: &mut Vec<FlattenedInstruction>, base: usize, stride: usize, dst: usize) {
+    let carry_down = [
+        FlattenedInstruction::Right(2),
+        shift(-(stride as isize)),
+        FlattenedInstruction::Left(stride + 2),
+    ];
+    let mut body = vec![
+        FlattenedInstruction::Sub(1),
+        FlattenedInstruction::Right(1),
+        shift(1),
+        FlattenedInstruction::Left(1),
+    ];
+    body.extend(carry_down.clone());
+    body.push(FlattenedInstruction::Loop(carry_down.to_vec()));
+
+    out.extend([
+        FlattenedInstruction::Goto(base + stride),
+        FlattenedInstruction::Loop(vec![FlattenedInstruction::Right(stride)]),
+        FlattenedInstruction::Left(stride),
+        FlattenedInstruction::Loop(body),
+        FlattenedInstruction::Sync(base),
+    ]);
+    clear(out, dst);
+    transfer(out, base + 2, &[dst]);
+}
+
+/* calcule les résultats dans des cellules de travail puis les déplace dans les destinations,
+    ce qui permet à une destination d'être aussi une entrée
+*/
+fn into_cells(
+    out: &mut Vec<FlattenedInstruction>,
+    dsts: &[usize],
+    scratch: &mut Scratch,
+    compute: impl FnOnce(&mut Vec<FlattenedInstruction>, &[usize], &mut Scratch),
+) {
+    let mark = scratch.used;
+    let res = dsts.iter().map(|_| scratch.alloc()).collect::<Vec<_>>();
+    compute(out, &res, scratch);
+    res.iter().zip(dsts).for_each(|(tmp, dst)| {
+        clear(out, *dst);
+        transfer(out, *tmp, &[*dst]);
+    });
+    scratch.used = mark;
+}
+
+/* remplace un appel de built-in, la tête de lecture finit toujours sur la dernière cellule passée en argument */
+fn lower(
+    out: &mut Vec<FlattenedInstruction>,
+    builtin: Builtin,
+    args: &[usize],
+    layout: &Layout,
+    scratch: &mut Scratch,
+) -> Result<()> {
+    if !builtin.supports_wide()
+        && builtin
+            .cell_args(args)
+            .iter()
+            .any(|cell| layout.width_of(*cell) > 1)
+    {
+        return Err(anyhow!(
+            "{:?} does not support multi-cell variables",
+            builtin
+        ));
+    }
+    if matches!(builtin, Builtin::Load | Builtin::Store) && layout.array_len(args[0]).is_none() {
+        return Err(anyhow!("{:?} expects an array as first argument", builtin));
+    }
+    if matches!(builtin, Builtin::Push | Builtin::Pop | Builtin::IsEmpty)
+        && args[0] >= layout.stack_count()
+    {
+        return Err(anyhow!("{:?} expects a stack as first argument", builtin));
+    }
+    let stride = 3 * layout.stack_count();
+    let stack = scratch.stacks + 3 * args[0];
+    let wide = |cell: usize| (cell, layout.width_of(cell));
+
+    match builtin {
+        Builtin::Add => add_const(
+            out,
+            args[0],
+            layout.width_of(args[0]),
+            args[1],
+            false,
+            scratch,
+        ),
+        Builtin::Sub => add_const(
+            out,
+            args[0],
+            layout.width_of(args[0]),
+            args[1],
+            true,
+            scratch,
+        ),
+        Builtin::SetConst => set_const(out, args[0], layout.width_of(args[0]), args[1]),
+        Builtin::PrintNum if layout.width_of(args[0]) > 1 => {
+            print_num_wide(out, args[0], layout.width_of(args[0]), scratch)
+        }
+        Builtin::PrintNum => print_num(out, args[0], scratch),
+        Builtin::ReadNum => read_num(out, args[0], scratch),
+        Builtin::Mul => into_cells(out, &args[2..], scratch, |out, res, scratch| {
+            mul(out, args[0], args[1], res[0], scratch)
+        }),
+        Builtin::DivMod => into_cells(out, &args[2..], scratch, |out, res, scratch| {
+            divmod(out, args[0], args[1], res[0], res[1], scratch)
+        }),
+        Builtin::Eq | Builtin::Lt => {
+            into_cells(out, &args[2..], scratch, |out, res, scratch| {
+                match (builtin, wide(args[0]), wide(args[1])) {
+                    (Builtin::Eq, (a, 1), (b, 1)) => equal(out, a, b, res[0], scratch),
+                    (Builtin::Eq, a, b) => equal_wide(out, a, b, res[0], scratch),
+                    (_, (a, 1), (b, 1)) => less_than(out, a, b, res[0], scratch),
+                    (_, a, b) => less_than_wide(out, a, b, res[0], scratch),
+                }
+            });
+            /* le résultat est dans l'octet de poids faible d'une destination sur plusieurs cellules */
+            (1..layout.width_of(args[2])).for_each(|i| clear(out, args[2] + i));
+        }
+        Builtin::Not => into_cells(out, &args[1..], scratch, |out, res, scratch| {
+            not(out, args[0], res[0], scratch)
+        }),
+        Builtin::Load => {
+            let mut outside = Vec::new();
+            clear(&mut outside, args[2]);
+            in_bounds(
+                out,
+                args[1],
+                layout.array_len(args[0]).unwrap(),
+                scratch,
+                |out, scratch| load(out, args[0], args[1], args[2], scratch),
+                outside,
+            )
+        }
+        Builtin::Store => in_bounds(
+            out,
+            args[1],
+            layout.array_len(args[0]).unwrap(),
+            scratch,
+            |out, scratch| store(out, args[0], args[1], args[2], scratch),
+            Vec::new(),
+        ),
+        Builtin::Push => push(out, stack, stride, args[1], scratch),
+        Builtin::Pop => pop(out, stack, stride, args[1]),
+        Builtin::IsEmpty => into_cells(out, &args[1..], scratch, |out, res, scratch| {
+            not(out, stack + stride, res[0], scratch)
+        }),
+    }
+    let cells = builtin.cell_args(args);
+    out.push(FlattenedInstruction::Goto(cells[cells.len() - 1]));
+    Ok(())
+}
+
+fn expand(
+    ist_list: Vec<FlattenedInstruction>,
+    layout: &Layout,
+    scratch: &mut Scratch,
+) -> Result<Vec<FlattenedInstruction>> {
+    let mut out = Vec::new();
+    for ist in ist_list {
+        match ist {
+            FlattenedInstruction::Builtin(builtin, args) => {
+                lower(&mut out, builtin, &args, layout, scratch)?
+            }
+            FlattenedInstruction::Loop(inner) => {
+                out.push(FlattenedInstruction::Loop(expand(inner, layout, scratch)?))
+            }
+            e => out.push(e),
+        }
+    }
+    Ok(out)
+}
+
+/* fonction permettant de vérifier que le programme peut être compilé pour des cellules du type donné
+    les expansions des built-ins et des variables sur plusieurs cellules supposent des cellules de 8 bits (retenues et constantes modulo 256)
+*/
+pub fn check_cell_type(
+    ist_list: &[FlattenedInstruction],
+    layout: &Layout,
+    cell: VarType,
+) -> Result<()> {
+    fn uses_builtin(ist_list: &[FlattenedInstruction]) -> bool {
+        ist_list.iter().any(|ist| match ist {
+            FlattenedInstruction::Builtin(_, _) => true,
+            FlattenedInstruction::Loop(inner) => uses_builtin(inner),
+            _ => false,
+        })
+    }
+    if cell == VarType::U8 {
+        Ok(())
+    } else if layout.has_wide_variables() {
+        Err(anyhow!(
+            "--cell={} is not supported by multi-cell variables, which need 8-bit cells",
+            cell.name()
+        ))
+    } else if uses_builtin(ist_list) {
+        Err(anyhow!(
+            "--cell={} is not supported by built-ins, which need 8-bit cells",
+            cell.name()
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/* fonction permettant de remplacer chaque appel de built-in par son expansion en instructions de base
+    les cellules de travail sont placées juste après la plus grande cellule adressée par le programme et les variables déclarées,
+    les piles juste après les cellules de travail
+*/
+pub fn expand_builtin(
+    ist_list: Vec<FlattenedInstruction>,
+    layout: &Layout,
+) -> Result<Vec<FlattenedInstruction>> {
+    let mut scratch = Scratch {
+        base: (max_cell(&ist_list) + 1).max(layout.end()),
+        used: 0,
+        peak: 0,
+        stacks: 0,
+    };
+    if layout.stack_count() > 0 {
+        /* une première expansion donne le nombre de cellules de travail, et donc le début des piles */
+        expand(ist_list.clone(), layout, &mut scratch)?;
+        scratch.stacks = scratch.base + scratch.peak;
+    }
+    expand(ist_list, layout, &mut scratch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code_checker::{transform_goto, Outputpointer};
+    use crate::interpreter::run;
+    use crate::optimizer::optimize_consecutive;
+    use crate::producer::produce;
+
+    fn compile(ist_list: Vec<FlattenedInstruction>) -> String {
+        compile_with(ist_list, &Layout::default())
+    }
+
+    fn compile_with(ist_list: Vec<FlattenedInstruction>, layout: &Layout) -> String {
+        let ist_list = expand_builtin(ist_list, layout).unwrap();
+        let (res, _) = transform_goto(ist_list, Outputpointer::default());
+        produce(optimize_consecutive(res.expect("failed to transform goto"))).unwrap()
+    }
+
+    #[test]
+    fn printnum_prints_every_value() {
+        for value in 0..=255 {
+            let code = compile(vec![
+                FlattenedInstruction::Goto(2),
+                FlattenedInstruction::SetConst(value),
+                FlattenedInstruction::Builtin(Builtin::PrintNum, vec![2]),
+            ]);
+            let machine = run(&code, &[]).unwrap();
+            assert_eq!(machine.output, value.to_string().into_bytes());
+            assert_eq!(machine.tape[2] as usize, value);
+            assert!(machine.tape[3..].iter().all(|cell| *cell == 0));
+        }
+    }
+
+    #[test]
+    fn readnum_reads_every_value() {
+        for value in 0..=255 {
+            let code = compile(vec![
+                FlattenedInstruction::Goto(1),
+                FlattenedInstruction::SetConst(42),
+                FlattenedInstruction::Builtin(Builtin::ReadNum, vec![1]),
+            ]);
+            let machine = run(&code, format!("{}\n", value).as_bytes()).unwrap();
+            assert_eq!(machine.tape[1] as usize, value);
+            assert!(machine.tape[2..].iter().all(|cell| *cell == 0));
+        }
+    }
+
+    /* exécute le built-in sur les cellules 0 et 1 initialisées à a et b, les résultats vont dans les cellules suivantes */
+    fn eval(builtin: Builtin, a: usize, b: usize) -> Vec<u8> {
+        let args = (0..builtin.arity()).collect::<Vec<_>>();
+        let code = compile(vec![
+            FlattenedInstruction::Goto(0),
+            FlattenedInstruction::SetConst(a),
+            FlattenedInstruction::Goto(1),
+            FlattenedInstruction::SetConst(b),
+            FlattenedInstruction::Builtin(builtin, args.clone()),
+        ]);
+        let machine = run(&code, &[]).unwrap();
+        assert_eq!(machine.pointer, args[args.len() - 1]);
+        assert!(machine.tape[args.len().max(2)..]
+            .iter()
+            .all(|cell| *cell == 0));
+        machine.tape[..args.len().max(2)].to_vec()
+    }
+
+    #[test]
+    fn arithmetic_builtins_keep_inputs() {
+        for a in (0..=255).step_by(15) {
+            for b in (0..=255).step_by(17) {
+                let (a8, b8) = (a as u8, b as u8);
+                assert_eq!(eval(Builtin::Mul, a, b), [a8, b8, a8.wrapping_mul(b8)]);
+                let (q, r) = if b == 0 { (0, a8) } else { (a8 / b8, a8 % b8) };
+                assert_eq!(eval(Builtin::DivMod, a, b), [a8, b8, q, r]);
+                assert_eq!(eval(Builtin::Eq, a, b), [a8, b8, (a == b) as u8]);
+                assert_eq!(eval(Builtin::Lt, a, b), [a8, b8, (a < b) as u8]);
+                assert_eq!(eval(Builtin::Not, a, 0), [a8, (a == 0) as u8]);
+            }
+        }
+    }
+
+    #[test]
+    fn builtin_destination_can_be_an_input() {
+        let code = compile(vec![
+            FlattenedInstruction::Goto(0),
+            FlattenedInstruction::SetConst(6),
+            FlattenedInstruction::Goto(1),
+            FlattenedInstruction::SetConst(7),
+            FlattenedInstruction::Builtin(Builtin::Mul, vec![0, 1, 0]),
+            FlattenedInstruction::Builtin(Builtin::Lt, vec![1, 0, 1]),
+        ]);
+        let machine = run(&code, &[]).unwrap();
+        assert_eq!(machine.tape[..2], [42, 1]);
+    }
+
+    #[test]
+    fn readnum_stops_at_end_of_input() {
+        let code = compile(vec![FlattenedInstruction::Builtin(
+            Builtin::ReadNum,
+            vec![0],
+        )]);
+        let machine = run(&code, b"107").unwrap();
+        assert_eq!(machine.tape[0], 107);
+    }
+
+    /* x: u16 en 0, y: u32 en 2, les cellules 6 et 7 sont simples */
+    fn wide_layout() -> Layout {
+        let mut layout = Layout::default();
+        layout.declare("x", VarType::U16).unwrap();
+        layout.declare("y", VarType::U32).unwrap();
+        layout
+    }
+
+    #[test]
+    fn wide_arithmetic_propagates_carry() {
+        let layout = wide_layout();
+        let cases = [
+            (0, 1, 300),
+            (255, 1, 0),
+            (65535, 1, 1),
+            (1000, 60000, 0),
+            (256, 0, 1),
+        ];
+        for (start, add, sub) in cases {
+            let code = compile_with(
+                vec![
+                    FlattenedInstruction::Builtin(Builtin::SetConst, vec![0, start]),
+                    FlattenedInstruction::Builtin(Builtin::Add, vec![0, add]),
+                    FlattenedInstruction::Builtin(Builtin::Sub, vec![0, sub]),
+                    FlattenedInstruction::Builtin(Builtin::SetConst, vec![2, start * 65599]),
+                    FlattenedInstruction::Builtin(Builtin::Sub, vec![2, sub * 70000]),
+                    FlattenedInstruction::Builtin(Builtin::PrintNum, vec![0]),
+                    FlattenedInstruction::Builtin(Builtin::PrintNum, vec![2]),
+                ],
+                &layout,
+            );
+            let machine = run(&code, &[]).unwrap();
+            let x = (start as u16)
+                .wrapping_add(add as u16)
+                .wrapping_sub(sub as u16);
+            let y = (start as u32)
+                .wrapping_mul(65599)
+                .wrapping_sub(sub as u32 * 70000);
+            assert_eq!(machine.output, format!("{}{}", x, y).into_bytes());
+            assert!(machine.tape[8..].iter().all(|cell| *cell == 0));
+        }
+    }
+
+    #[test]
+    fn wide_comparison() {
+        let layout = wide_layout();
+        let cases = [
+            (0, 0),
+            (1, 256),
+            (256, 1),
+            (65535, 65535),
+            (300, 65836),
+            (70000, 4464),
+        ];
+        for (x, y) in cases {
+            let code = compile_with(
+                vec![
+                    FlattenedInstruction::Builtin(Builtin::SetConst, vec![0, x]),
+                    FlattenedInstruction::Builtin(Builtin::SetConst, vec![2, y]),
+                    FlattenedInstruction::Builtin(Builtin::Eq, vec![0, 2, 6]),
+                    FlattenedInstruction::Builtin(Builtin::Lt, vec![0, 2, 7]),
+                ],
+                &layout,
+            );
+            let machine = run(&code, &[]).unwrap();
+            let (x, y) = (x as u16 as u32, y as u32);
+            assert_eq!(machine.tape[6..8], [(x == y) as u8, (x < y) as u8]);
+        }
+    }
+
+    #[test]
+    fn array_store_then_load() {
+        let mut layout = Layout::default();
+        layout.declare("i", VarType::U8).unwrap();
+        layout.declare("v", VarType::U8).unwrap();
+        layout.declare_array("arr", 5).unwrap();
+        let mut ist_list = Vec::new();
+        for i in 0..5 {
+            ist_list.extend([
+                FlattenedInstruction::Builtin(Builtin::SetConst, vec![0, i]),
+                FlattenedInstruction::Builtin(Builtin::SetConst, vec![1, 10 * i + 7]),
+                FlattenedInstruction::Builtin(Builtin::Store, vec![2, 0, 1]),
+            ]);
+        }
+        for i in [3, 0, 4] {
+            ist_list.extend([
+                FlattenedInstruction::Builtin(Builtin::SetConst, vec![0, i]),
+                FlattenedInstruction::Builtin(Builtin::Load, vec![2, 0, 1]),
+                FlattenedInstruction::Builtin(Builtin::PrintNum, vec![1]),
+            ]);
+        }
+        let machine = run(&compile_with(ist_list, &layout), &[]).unwrap();
+        assert_eq!(machine.output, b"37747");
+        let elements = (0..5).map(|k| machine.tape[2 + 3 * (k + 1) + 2]);
+        assert!(elements.eq([7, 17, 27, 37, 47]));
+    }
+
+    #[test]
+    fn array_index_out_of_bounds() {
+        let mut layout = Layout::default();
+        layout.declare("i", VarType::U8).unwrap();
+        layout.declare("v", VarType::U8).unwrap();
+        layout.declare_array("arr", 2).unwrap();
+        layout.declare("z", VarType::U8).unwrap();
+        layout.declare("w", VarType::U8).unwrap();
+        let (z, w) = (2 + 3 * 3, 2 + 3 * 3 + 1);
+        let mut ist_list = vec![
+            FlattenedInstruction::Builtin(Builtin::SetConst, vec![z, 7]),
+            FlattenedInstruction::Builtin(Builtin::SetConst, vec![w, 7]),
+        ];
+        for i in [5, 2, 1] {
+            ist_list.extend([
+                FlattenedInstruction::Builtin(Builtin::SetConst, vec![0, i]),
+                FlattenedInstruction::Builtin(Builtin::SetConst, vec![1, 9]),
+                FlattenedInstruction::Builtin(Builtin::Store, vec![2, 0, 1]),
+                FlattenedInstruction::Builtin(Builtin::Load, vec![2, 0, 1]),
+                FlattenedInstruction::Builtin(Builtin::PrintNum, vec![1]),
+            ]);
+        }
+        ist_list.extend([
+            FlattenedInstruction::Builtin(Builtin::PrintNum, vec![z]),
+            FlattenedInstruction::Builtin(Builtin::PrintNum, vec![w]),
+        ]);
+        let machine = run(&compile_with(ist_list, &layout), &[]).unwrap();
+        //les index 5 et 2 sont hors du tableau: rien n'est écrit et la lecture donne 0
+        assert_eq!(machine.output, b"00977");
+    }
+
+    #[test]
+    fn interleaved_stacks() {
+        let mut layout = Layout::default();
+        layout.declare("v", VarType::U8).unwrap();
+        layout.declare("flag", VarType::U8).unwrap();
+        layout.declare_stack("s").unwrap();
+        layout.declare_stack("t").unwrap();
+        let mut ist_list = Vec::new();
+        for value in [5, 250, 1] {
+            ist_list.extend([
+                FlattenedInstruction::Builtin(Builtin::SetConst, vec![0, value]),
+                FlattenedInstruction::Builtin(Builtin::Push, vec![0, 0]),
+                FlattenedInstruction::Builtin(Builtin::Add, vec![0, 1]),
+                FlattenedInstruction::Builtin(Builtin::Push, vec![1, 0]),
+            ]);
+        }
+        for stack in [1, 0] {
+            for _ in 0..4 {
+                ist_list.extend([
+                    FlattenedInstruction::Builtin(Builtin::IsEmpty, vec![stack, 1]),
+                    FlattenedInstruction::Builtin(Builtin::PrintNum, vec![1]),
+                    FlattenedInstruction::Builtin(Builtin::Pop, vec![stack, 0]),
+                    FlattenedInstruction::Builtin(Builtin::PrintNum, vec![0]),
+                ]);
+            }
+        }
+        let machine = run(&compile_with(ist_list, &layout), &[]).unwrap();
+        assert_eq!(machine.output, b"02025106100102500510");
+    }
+
+    /* les expansions supposent des cellules de 8 bits: setconst(x,250) add(x,750) afficherait 488 avec des cellules de 16 bits */
+    #[test]
+    fn builtins_need_byte_cells() {
+        let ist_list = vec![
+            FlattenedInstruction::Builtin(Builtin::SetConst, vec![0, 250]),
+            FlattenedInstruction::Builtin(Builtin::Add, vec![0, 750]),
+            FlattenedInstruction::Loop(vec![FlattenedInstruction::Builtin(
+                Builtin::PrintNum,
+                vec![0],
+            )]),
+        ];
+        let layout = Layout::default();
+        assert!(check_cell_type(&ist_list, &layout, VarType::U8).is_ok());
+        assert!(check_cell_type(&ist_list, &layout, VarType::U16).is_err());
+        assert!(check_cell_type(&ist_list[2..], &layout, VarType::U32).is_err());
+        let plain = vec![FlattenedInstruction::Add(750), FlattenedInstruction::Print];
+        assert!(check_cell_type(&plain, &layout, VarType::U16).is_ok());
+        assert!(check_cell_type(&plain, &wide_layout(), VarType::U16).is_err());
+        assert!(check_cell_type(&plain, &wide_layout(), VarType::U8).is_ok());
+    }
+}
@@ -0,0 +1,398 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+
+use crate::backend::{Target, TAPE_SIZE};
+use crate::dialect::Dialect;
+use crate::layout::VarType;
+use crate::optimizer::OptLevel;
+use crate::producer::Format;
+
+/* forme sous laquelle on écrit le programme compilé */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Bf,       //le code de la cible choisie avec --target
+    Flat,     //les instructions aplaties après optimisation
+    AstJson,  //l'arbre syntaxique du source en JSON
+    FlatJson, //les instructions aplaties après optimisation en JSON
+    Ir,       //les instructions aplaties après optimisation sous forme textuelle
+}
+
+impl Emit {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "bf" => Ok(Self::Bf),
+            "flat" => Ok(Self::Flat),
+            "ast-json" => Ok(Self::AstJson),
+            "flat-json" => Ok(Self::FlatJson),
+            "ir" => Ok(Self::Ir),
+            _ => Err(anyhow!("unknown output kind {}", name)),
+        }
+    }
+
+    #[inline]
+    pub const fn extension(&self) -> &'static str {
+        match self {
+            Self::Bf => "bf",
+            Self::Flat => "flat",
+            Self::AstJson | Self::FlatJson => "json",
+            Self::Ir => "ir",
+        }
+    }
+}
+
+/* langage du fichier lu */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Bfil,
+    Bf,       //du Brainfuck, seulement optimisé avant d'être écrit
+    FlatJson, //des instructions aplaties en JSON, telles qu'écrites par --emit=flat-json
+    Ir,       //des instructions aplaties sous forme textuelle, telles qu'écrites par --emit=ir
+}
+
+impl InputFormat {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "bfil" => Ok(Self::Bfil),
+            "bf" => Ok(Self::Bf),
+            "flat-json" => Ok(Self::FlatJson),
+            "ir" => Ok(Self::Ir),
+            _ => Err(anyhow!("unknown input format {}", name)),
+        }
+    }
+}
+
+/* options de la ligne de commande:
+    BF_IL [run [--fast] | decompile] [fichier.bfil] [--input-format=bfil|bf|flat-json|ir] [-o sortie] [--emit=bf|flat|ast-json|flat-json|ir] [--map=fichier] [-O0|-O1|-O2|-Os] [--pass=nom] [--no-pass=nom] [--stats]
+        [--format=raw|minified|wrapped|pretty] [--width=n] [--annotate] [--target=bf|c|rust|wat|x86_64-asm]
+        [--cell=u8|u16|u32] [--tape-size=n] [--dialect=bf|debug|ook|blub] [--token-map=fichier]
+    sans fichier on compile test.bfil, la sortie prend par défaut le nom de l'entrée avec l'extension correspondant à --emit et --target, -o - écrit sur la sortie standard
+    avec --input-format=bf l'entrée est du Brainfuck passé seulement par les optimisations, si la sortie par défaut est l'entrée elle même
+    son nom reçoit le suffixe .opt
+    --emit=ast-json écrit l'arbre syntaxique du source BF-IL, --emit=flat-json les instructions aplaties après optimisation
+    que --input-format=flat-json relit pour les optimiser et les produire, --emit=ir et --input-format=ir font de même
+    avec une instruction par ligne
+    --map écrit la position choisie pour chaque variable, --stats affiche ce qu'a fait chaque passe d'optimisation
+    --width donne la largeur des lignes pour les formats wrapped et pretty
+    --cell et --tape-size donnent le type des cellules et la taille de la bande du module produit par --target=rust, --cell n'est accepté
+    que par cette cible car toutes les autres, comme run, utilisent des cellules de 8 bits, et seulement pour un programme sans built-in
+    ni variable sur plusieurs cellules dont les expansions supposent des cellules de 8 bits
+    --tape-size donne aussi la mémoire du module produit par --target=wat et la bande du programme produit par --target=x86_64-asm
+    --dialect choisit les mots écrits pour chaque commande Brainfuck, --token-map les lit dans un fichier, tous deux demandent --target=bf
+    et --emit=bf
+    run exécute le programme compilé au lieu de l'écrire, l'entrée et la sortie étant celles du terminal
+    avec --fast il est exécuté par la machine virtuelle au lieu de l'interpréteur Brainfuck naïf
+    decompile lit un programme Brainfuck et écrit le BF-IL correspondant, par défaut dans le fichier d'extension bfil
+    --annotate ajoute avant le code de chaque ligne du source un commentaire donnant cette ligne, les fonctions appelées et la cellule courante
+*/
+#[derive(Debug, PartialEq, Eq)]
+pub struct Options {
+    pub run: bool,
+    pub fast: bool,
+    pub decompile: bool,
+    pub input: String,
+    pub input_format: InputFormat,
+    pub output: String,
+    pub emit: Emit,
+    pub map: Option<String>,
+    pub level: OptLevel,
+    pub passes: Vec<String>,    //passes ajoutées au niveau choisi
+    pub no_passes: Vec<String>, //passes retirées du niveau choisi
+    pub stats: bool,
+    pub format: Format,
+    pub width: usize,
+    pub annotate: bool,
+    pub target: Target,
+    pub cell: VarType,
+    pub tape_size: usize,
+    pub dialect: Dialect,
+    pub token_map: Option<String>,
+}
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut args = args.into_iter().peekable();
+        let run = args.next_if(|arg| arg == "run").is_some();
+        let decompile = !run && args.next_if(|arg| arg == "decompile").is_some();
+        let mut fast = false;
+        let mut input = String::from("test.bfil");
+        let mut input_format = InputFormat::Bfil;
+        let mut output = None;
+        let mut emit = Emit::Bf;
+        let mut map = None;
+        let mut level = OptLevel::O2;
+        let mut passes = Vec::new();
+        let mut no_passes = Vec::new();
+        let mut stats = false;
+        let mut format = Format::Raw;
+        let mut width = 80;
+        let mut annotate = false;
+        let mut target = Target::Bf;
+        let mut cell = None;
+        let mut tape_size = TAPE_SIZE;
+        let mut dialect = None;
+        let mut token_map = None;
+
+        while let Some(arg) = args.next() {
+            if arg == "-o" {
+                output = Some(
+                    args.next()
+                        .ok_or_else(|| anyhow!("missing file after -o"))?,
+                );
+            } else if let Some(name) = arg.strip_prefix("--input-format=") {
+                input_format = InputFormat::parse(name)?;
+            } else if let Some(kind) = arg.strip_prefix("--emit=") {
+                emit = Emit::parse(kind)?;
+            } else if let Some(file) = arg.strip_prefix("--map=") {
+                map = Some(file.to_owned());
+            } else if let Some(name) = arg.strip_prefix("-O") {
+                level = OptLevel::parse(name)?;
+            } else if let Some(name) = arg.strip_prefix("--pass=") {
+                passes.push(name.to_owned());
+            } else if let Some(name) = arg.strip_prefix("--no-pass=") {
+                no_passes.push(name.to_owned());
+            } else if arg == "--stats" {
+                stats = true;
+            } else if let Some(name) = arg.strip_prefix("--format=") {
+                format = Format::parse(name)?;
+            } else if let Some(n) = arg.strip_prefix("--width=") {
+                width = n.parse().map_err(|_| anyhow!("invalid line width {}", n))?;
+            } else if let Some(name) = arg.strip_prefix("--dialect=") {
+                dialect = Some(Dialect::parse(name)?);
+            } else if let Some(file) = arg.strip_prefix("--token-map=") {
+                token_map = Some(file.to_owned());
+            } else if arg == "--fast" && run {
+                fast = true;
+            } else if arg == "--annotate" {
+                annotate = true;
+            } else if let Some(name) = arg.strip_prefix("--target=") {
+                target = Target::parse(name)?;
+            } else if let Some(name) = arg.strip_prefix("--cell=") {
+                cell = Some(VarType::parse(name)?);
+            } else if let Some(n) = arg.strip_prefix("--tape-size=") {
+                tape_size = n.parse().map_err(|_| anyhow!("invalid tape size {}", n))?;
+            } else if arg.starts_with('-') {
+                return Err(anyhow!("unknown option {}", arg));
+            } else {
+                input = arg;
+            }
+        }
+
+        //les autres cibles ont des cellules de 8 bits, l'optimiseur ne doit pas supposer un autre débordement
+        if cell.is_some() && (target != Target::Rust || run) {
+            return Err(anyhow!("--cell is only supported by --target=rust"));
+        }
+        let cell = cell.unwrap_or(VarType::U8);
+        //les dialectes ne changent que l'écriture du Brainfuck
+        if (dialect.is_some() || token_map.is_some()) && (target != Target::Bf || emit != Emit::Bf)
+        {
+            return Err(anyhow!(
+                "--dialect and --token-map only apply to brainfuck output"
+            ));
+        }
+        let dialect = dialect.unwrap_or(Dialect::Brainfuck);
+        if emit == Emit::AstJson && input_format != InputFormat::Bfil {
+            return Err(anyhow!("--emit=ast-json needs a BF-IL source"));
+        }
+        let output = output.unwrap_or_else(|| {
+            let extension = match emit {
+                _ if decompile => "bfil",
+                Emit::Bf => target.extension(),
+                _ => emit.extension(),
+            };
+            let path = Path::new(&input);
+            //on n'écrase pas le Brainfuck que l'on optimise
+            let path = match path.with_extension(extension) {
+                same if same == path => path.with_extension(format!("opt.{}", extension)),
+                other => other,
+            };
+            path.to_string_lossy().into_owned()
+        });
+        Ok(Self {
+            run,
+            fast,
+            decompile,
+            input,
+            input_format,
+            output,
+            emit,
+            map,
+            level,
+            passes,
+            no_passes,
+            stats,
+            format,
+            width,
+            annotate,
+            target,
+            cell,
+            tape_size,
+            dialect,
+            token_map,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_defaults() {
+        assert_eq!(
+            parse(&[]).unwrap(),
+            Options {
+                run: false,
+                fast: false,
+                decompile: false,
+                input: "test.bfil".into(),
+                input_format: InputFormat::Bfil,
+                output: "test.bf".into(),
+                emit: Emit::Bf,
+                map: None,
+                level: OptLevel::O2,
+                passes: vec![],
+                no_passes: vec![],
+                stats: false,
+                format: Format::Raw,
+                width: 80,
+                annotate: false,
+                target: Target::Bf,
+                cell: VarType::U8,
+                tape_size: TAPE_SIZE,
+                dialect: Dialect::Brainfuck,
+                token_map: None
+            }
+        );
+        assert_eq!(
+            parse(&["prog.bfil", "-o", "out.bf"]).unwrap().output,
+            "out.bf"
+        );
+        assert_eq!(
+            parse(&["--map=prog.map"]).unwrap().map.as_deref(),
+            Some("prog.map")
+        );
+        let options = parse(&["--format=pretty", "--width=60"]).unwrap();
+        assert_eq!((options.format, options.width), (Format::Pretty, 60));
+        assert!(parse(&["--width=wide"]).is_err());
+        assert!(parse(&["--annotate"]).unwrap().annotate);
+        assert!(parse(&["-o"]).is_err());
+    }
+
+    #[test]
+    fn parse_run_and_decompile() {
+        assert!(parse(&["--fast"]).is_err());
+        let options = parse(&["run", "--fast", "prog.bfil"]).unwrap();
+        assert_eq!((options.run, options.fast), (true, true));
+        assert_eq!(options.input, "prog.bfil");
+        let options = parse(&["decompile", "prog.bf"]).unwrap();
+        assert!(options.decompile && !options.run);
+        assert_eq!(options.output, "prog.bfil");
+    }
+
+    #[test]
+    fn parse_levels_and_passes() {
+        let options = parse(&[
+            "-Os",
+            "--no-pass=dead-store",
+            "--pass=track-values",
+            "--stats",
+        ])
+        .unwrap();
+        assert_eq!(options.level, OptLevel::Os);
+        assert_eq!(options.no_passes, vec!["dead-store"]);
+        assert_eq!(options.passes, vec!["track-values"]);
+        assert!(options.stats);
+        assert!(parse(&["-O3"]).is_err());
+    }
+
+    #[test]
+    fn parse_target_and_cell() {
+        let options = parse(&["--target=c", "prog.bfil"]).unwrap();
+        assert_eq!(
+            (options.target, options.output.as_str()),
+            (Target::C, "prog.c")
+        );
+        assert!(parse(&["--target=cobol"]).is_err());
+        let options = parse(&["--target=rust", "--cell=u16", "--tape-size=1000"]).unwrap();
+        assert_eq!(
+            (options.output.as_str(), options.cell, options.tape_size),
+            ("test.rs", VarType::U16, 1000)
+        );
+        assert!(parse(&["--cell=u7"]).is_err());
+        assert!(parse(&["--cell=u16"]).is_err());
+        assert!(parse(&["--cell=u16", "--target=c"]).is_err());
+        assert!(parse(&["run", "--cell=u16", "--target=rust"]).is_err());
+    }
+
+    #[test]
+    fn parse_dialect() {
+        let options = parse(&["--dialect=debug", "--token-map=ook.map"]).unwrap();
+        assert_eq!(options.dialect, Dialect::Debug);
+        assert_eq!(options.token_map.as_deref(), Some("ook.map"));
+        assert!(parse(&["--dialect=cow"]).is_err());
+        assert!(parse(&["--dialect=ook", "--target=c"]).is_err());
+        assert!(parse(&["--token-map=ook.map", "--emit=ir"]).is_err());
+    }
+
+    #[test]
+    fn parse_input_format() {
+        let options = parse(&["--input-format=bf", "prog.bf"]).unwrap();
+        assert_eq!(options.input_format, InputFormat::Bf);
+        assert_eq!(options.output, "prog.opt.bf");
+        assert_eq!(
+            parse(&["--input-format=bf", "prog.bf", "--target=c"])
+                .unwrap()
+                .output,
+            "prog.c"
+        );
+        assert!(parse(&["--input-format=c"]).is_err());
+        let options =
+            parse(&["--emit=flat-json", "--input-format=flat-json", "prog.json"]).unwrap();
+        assert_eq!(
+            (options.emit, options.input_format, options.output.as_str()),
+            (Emit::FlatJson, InputFormat::FlatJson, "prog.opt.json")
+        );
+        let options = parse(&["--emit=ir", "--input-format=ir", "prog.ir"]).unwrap();
+        assert_eq!(
+            (options.emit, options.input_format, options.output.as_str()),
+            (Emit::Ir, InputFormat::Ir, "prog.opt.ir")
+        );
+    }
+
+    #[test]
+    fn parse_emit() {
+        assert_eq!(
+            parse(&["--emit=flat", "prog.bfil"]).unwrap(),
+            Options {
+                run: false,
+                fast: false,
+                decompile: false,
+                input: "prog.bfil".into(),
+                input_format: InputFormat::Bfil,
+                output: "prog.flat".into(),
+                emit: Emit::Flat,
+                map: None,
+                level: OptLevel::O2,
+                passes: vec![],
+                no_passes: vec![],
+                stats: false,
+                format: Format::Raw,
+                width: 80,
+                annotate: false,
+                target: Target::Bf,
+                cell: VarType::U8,
+                tape_size: TAPE_SIZE,
+                dialect: Dialect::Brainfuck,
+                token_map: None
+            }
+        );
+        assert_eq!(parse(&["--emit=ast-json"]).unwrap().output, "test.json");
+        assert!(parse(&["--emit=ast-json", "--input-format=bf"]).is_err());
+        assert!(parse(&["--emit=wasm"]).is_err());
+    }
+}
@@ -1,10 +1,8 @@
 use std::ops::Add;
 
 use crate::function::FlattenedInstruction;
-use crate::variable::Variable;
 
-use anyhow::{anyhow, Context, Result};
-use either::Either;
+use anyhow::{anyhow, Result};
 
 /* structure permettant de suivre au cours des instructions le positionnement de la tête de lecture */
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
@@ -50,12 +48,14 @@ impl Add for Outputpointer {
 
 /* fonction permettant de faire évoluer au cours d'une instruction la position de la tête de lecture */
 fn add_position_single(start_pos: Outputpointer, ist: &FlattenedInstruction) -> Outputpointer {
-    dbg!(start_pos);
-    match dbg!(ist) {
+    match ist {
         FlattenedInstruction::Left(val) => start_pos + ((-(*val as isize)).into()),
         FlattenedInstruction::Right(val) => start_pos + (*val as isize).into(),
         FlattenedInstruction::Goto(val) => (*val as isize).into(),
-        FlattenedInstruction::Loop(inner) => unreachable!(), /* {
+        FlattenedInstruction::ScanLeft(_) | FlattenedInstruction::ScanRight(_) => {
+            Outputpointer::Unpredictable
+        }
+        FlattenedInstruction::Loop(_inner) => unreachable!(), /* {
         let final_pos = inner
         .iter()
         .fold(Outputpointer::default(), add_position_single);
@@ -98,6 +98,17 @@ pub fn transform_goto(
                         ))
                     }
                 }
+                FlattenedInstruction::Sync(cell) => {
+                    position = (cell as isize).into();
+                    Ok(FlattenedInstruction::Sync(cell))
+                }
+                FlattenedInstruction::Annotation(mut context) => {
+                    context.position = position
+                        .get_value()
+                        .ok()
+                        .and_then(|pos| usize::try_from(pos).ok());
+                    Ok(FlattenedInstruction::Annotation(context))
+                }
                 FlattenedInstruction::Loop(inner) => {
                     let (res, position_inner) = transform_goto(inner, position);
                     if position != position_inner {
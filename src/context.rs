@@ -1,11 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use crate::producer::COMMANDS;
+
 /*
     structure permmettant de conserver l'origine de chaque instruction afin de permettre de lever les erreurs correctement
+    et d'annoter le code produit: la ligne du source, les fonctions dont elle provient (la plus interne en premier)
+    et la position de la tête de lecture calculée par transform_goto
 */
-struct Context {
-    name: String,
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Context {
+    pub line: usize,
+    pub calls: Vec<String>,
+    pub position: Option<usize>,
 }
 
-enum ContextType {
-    FunctionDeclaration(String),
-    Goto,
+impl Context {
+    #[inline]
+    pub fn new(line: usize) -> Self {
+        Self {
+            line,
+            ..Self::default()
+        }
+    }
+
+    /* texte du commentaire, sans aucun caractère de commande brainfuck pour ne pas changer le programme */
+    pub fn describe(&self) -> String {
+        let mut res = format!("line {}", self.line);
+        for name in &self.calls {
+            res += &format!(" in {}", name);
+        }
+        match self.position {
+            Some(cell) => res += &format!(" at cell {}", cell),
+            None => res += " at unknown cell",
+        }
+        res.chars().filter(|c| !COMMANDS.contains(*c)).collect()
+    }
 }
@@ -0,0 +1,351 @@
+use anyhow::{anyhow, Result};
+
+use crate::function::FlattenedInstruction;
+use crate::optimizer::{OptLevel, PassManager};
+
+/* fonction permettant de lire du Brainfuck, les commandes répétées étant regroupées et les autres caractères ignorés */
+pub fn parse_brainfuck(code: &str) -> Result<Vec<FlattenedInstruction>> {
+    let mut stack = vec![Vec::new()];
+    for c in code.chars() {
+        let current = stack.last_mut().unwrap();
+        match (c, current.last_mut()) {
+            ('+', Some(FlattenedInstruction::Add(n)))
+            | ('-', Some(FlattenedInstruction::Sub(n)))
+            | ('<', Some(FlattenedInstruction::Left(n)))
+            | ('>', Some(FlattenedInstruction::Right(n))) => *n += 1,
+            ('+', _) => current.push(FlattenedInstruction::Add(1)),
+            ('-', _) => current.push(FlattenedInstruction::Sub(1)),
+            ('<', _) => current.push(FlattenedInstruction::Left(1)),
+            ('>', _) => current.push(FlattenedInstruction::Right(1)),
+            ('.', _) => current.push(FlattenedInstruction::Print),
+            (',', _) => current.push(FlattenedInstruction::Read),
+            ('[', _) => stack.push(Vec::new()),
+            (']', _) => {
+                let inner = stack.pop().unwrap();
+                stack
+                    .last_mut()
+                    .ok_or_else(|| anyhow!("unmatched ']' in Brainfuck code"))?
+                    .push(FlattenedInstruction::Loop(inner));
+            }
+            _ => (),
+        }
+    }
+    match stack.len() {
+        1 => Ok(stack.pop().unwrap()),
+        _ => Err(anyhow!("unmatched '[' in Brainfuck code")),
+    }
+}
+
+/* déplacement total de la tête de lecture, None s'il dépend des valeurs des cellules */
+fn net_movement(ist_list: &[FlattenedInstruction]) -> Option<isize> {
+    let mut res = 0;
+    for ist in ist_list {
+        match ist {
+            FlattenedInstruction::Right(n) => res += *n as isize,
+            FlattenedInstruction::Left(n) => res -= *n as isize,
+            FlattenedInstruction::Loop(inner) if net_movement(inner)? != 0 => return None,
+            FlattenedInstruction::ScanLeft(_) | FlattenedInstruction::ScanRight(_) => return None,
+            _ => (),
+        }
+    }
+    Some(res)
+}
+
+/* nom de la n-ième fonction générée, les noms de BF-IL ne contenant que des lettres */
+fn function_name(mut n: usize) -> String {
+    let mut suffix = String::new();
+    loop {
+        suffix.insert(0, (b'a' + (n % 26) as u8) as char);
+        if n < 26 {
+            break;
+        }
+        n = n / 26 - 1;
+    }
+    format!("loop{}", suffix)
+}
+
+fn indent(lines: Vec<String>) -> impl Iterator<Item = String> {
+    lines.into_iter().map(|line| format!("    {}", line))
+}
+
+/* ligne de commentaire décrivant une suite de multiplications, nommée comme l'idiome Brainfuck qu'elle réalise */
+fn describe_multiplication(targets: &[String], factors: &[isize]) -> String {
+    let kind = match (targets.len(), factors.iter().all(|factor| *factor == 1)) {
+        (1, true) => "move",
+        (_, true) => "copy",
+        _ => "multiply",
+    };
+    format!("// {} to {}", kind, targets.join(", "))
+}
+
+/* structure permettant d'écrire le BF-IL
+    tant que l'on connaît la position de la tête de lecture du Brainfuck, les cellules sont désignées par leur adresse
+    sinon on garde les déplacements relatifs, le langage n'autorisant pas les boucles imbriquées, chaque boucle contenue
+    dans une autre devient une fonction appelée une seule fois
+*/
+#[derive(Debug, Default)]
+struct Decompiler {
+    functions: Vec<String>,
+}
+
+/* suite de lignes en cours d'écriture avec la position de la tête de lecture du Brainfuck et celle du programme écrit */
+struct Block {
+    lines: Vec<String>,
+    position: Option<usize>,
+    head: Option<usize>,
+    nested: bool, //à l'intérieur d'une boucle
+}
+
+impl Block {
+    fn new(position: Option<usize>, nested: bool) -> Self {
+        Self {
+            lines: Vec::new(),
+            position,
+            head: position,
+            nested,
+        }
+    }
+
+    /* fonction permettant d'amener la tête de lecture du programme écrit sur celle du Brainfuck */
+    fn sync(&mut self) {
+        if let Some(cell) = self.position {
+            if self.head != Some(cell) {
+                self.lines.push(format!("goto({})", cell));
+                self.head = Some(cell);
+            }
+        }
+    }
+
+    /* opération sur la cellule courante, avec son adresse quand on la connaît */
+    fn operation(&mut self, name: &str, value: usize) {
+        match self.position {
+            Some(cell) => {
+                self.lines.push(format!("{}({}, {})", name, cell, value));
+                self.head = Some(cell);
+            }
+            None => self.lines.push(format!("{}({})", name, value)),
+        }
+    }
+}
+
+impl Decompiler {
+    /* fonction permettant d'ajouter une boucle, directement ou par l'appel d'une nouvelle fonction si elle est imbriquée */
+    fn push_loop(&mut self, block: &mut Block, mut body: Vec<String>) {
+        if body.is_empty() {
+            body.push(String::from("add(0)"));
+        }
+        let mut lines = vec![String::from("loop {")];
+        lines.extend(indent(body));
+        lines.push(String::from("}"));
+
+        if block.nested {
+            let name = function_name(self.functions.len());
+            let mut function = format!("fn {}() {{\n", name);
+            for line in indent(lines) {
+                function.push_str(&line);
+                function.push('\n');
+            }
+            function.push_str("}\n");
+            self.functions.push(function);
+            block.lines.push(format!("{}()", name));
+        } else {
+            block.lines.extend(lines);
+        }
+    }
+
+    fn decompile_block(&mut self, ist_list: &[FlattenedInstruction], block: &mut Block) {
+        let mut i = 0;
+        while i < ist_list.len() {
+            match &ist_list[i] {
+                FlattenedInstruction::Add(n) => block.operation("add", *n),
+                FlattenedInstruction::Sub(n) => block.operation("sub", *n),
+                FlattenedInstruction::SetConst(v) => block.operation("setconst", *v),
+                FlattenedInstruction::Clear => block.operation("setconst", 0),
+                FlattenedInstruction::Right(n) => match block.position {
+                    Some(cell) => block.position = Some(cell + n),
+                    None => block.lines.push(format!("right({})", n)),
+                },
+                FlattenedInstruction::Left(n) => match block.position {
+                    Some(cell) if cell >= *n => block.position = Some(cell - n),
+                    Some(_) => {
+                        //on ne sait plus où l'on est, on continue en relatif depuis la position actuelle
+                        block.sync();
+                        block.position = None;
+                        block.lines.push(format!("left({})", n));
+                    }
+                    None => block.lines.push(format!("left({})", n)),
+                },
+                FlattenedInstruction::Print | FlattenedInstruction::Read => {
+                    block.sync();
+                    block.lines.push(String::from(
+                        if ist_list[i] == FlattenedInstruction::Print {
+                            "print()"
+                        } else {
+                            "read()"
+                        },
+                    ));
+                }
+                FlattenedInstruction::ScanLeft(stride)
+                | FlattenedInstruction::ScanRight(stride) => {
+                    block.sync();
+                    let direction = match ist_list[i] {
+                        FlattenedInstruction::ScanLeft(_) => "left",
+                        _ => "right",
+                    };
+                    self.push_loop(block, vec![format!("{}({})", direction, stride)]);
+                    block.position = None;
+                }
+                FlattenedInstruction::MulAdd { .. } => {
+                    let start = i;
+                    while let FlattenedInstruction::MulAdd { .. } = ist_list[i] {
+                        i += 1;
+                    }
+                    let multiplications = ist_list[start..i]
+                        .iter()
+                        .map(|ist| match ist {
+                            FlattenedInstruction::MulAdd { offset, factor } => (*offset, *factor),
+                            _ => unreachable!(),
+                        })
+                        .collect::<Vec<_>>();
+                    self.multiplication(block, &multiplications);
+                }
+                FlattenedInstruction::Loop(inner) => {
+                    block.sync();
+                    let balanced = net_movement(inner) == Some(0);
+                    let mut body = Block::new(block.position.filter(|_| balanced), true);
+                    self.decompile_block(inner, &mut body);
+                    body.sync();
+                    self.push_loop(block, body.lines);
+                    if !balanced {
+                        block.position = None;
+                    }
+                }
+                FlattenedInstruction::Noop
+                | FlattenedInstruction::Sync(_)
+                | FlattenedInstruction::Annotation(_) => (),
+                FlattenedInstruction::Goto(_) | FlattenedInstruction::Builtin(_, _) => {
+                    unreachable!()
+                }
+            }
+            i += 1;
+        }
+    }
+
+    /* fonction permettant d'écrire les multiplications reconnues par l'optimiseur, suivies du Clear qui les termine */
+    fn multiplication(&mut self, block: &mut Block, multiplications: &[(isize, isize)]) {
+        block.sync();
+        let factors = multiplications.iter().map(|(_, f)| *f).collect::<Vec<_>>();
+        let mut body = Vec::new();
+        match block.position {
+            Some(cell) if multiplications.iter().all(|(o, _)| cell as isize + o >= 0) => {
+                let targets = multiplications
+                    .iter()
+                    .map(|(offset, _)| (cell as isize + offset).to_string())
+                    .collect::<Vec<_>>();
+                block
+                    .lines
+                    .push(describe_multiplication(&targets, &factors));
+                body.push(format!("sub({}, 1)", cell));
+                for (target, factor) in targets.iter().zip(&factors) {
+                    match factor {
+                        f if *f >= 0 => body.push(format!("add({}, {})", target, f)),
+                        f => body.push(format!("sub({}, {})", target, f.unsigned_abs())),
+                    }
+                }
+                body.push(format!("goto({})", cell));
+            }
+            _ => {
+                let targets = multiplications
+                    .iter()
+                    .map(|(offset, _)| format!("{:+}", offset))
+                    .collect::<Vec<_>>();
+                block
+                    .lines
+                    .push(describe_multiplication(&targets, &factors));
+                body.push(String::from("sub(1)"));
+                let mut offset = 0;
+                for (target, factor) in multiplications {
+                    match target - offset {
+                        d if d >= 0 => body.push(format!("right({})", d)),
+                        d => body.push(format!("left({})", d.unsigned_abs())),
+                    }
+                    match factor {
+                        f if *f >= 0 => body.push(format!("add({})", f)),
+                        f => body.push(format!("sub({})", f.unsigned_abs())),
+                    }
+                    offset = *target;
+                }
+                match offset {
+                    d if d >= 0 => body.push(format!("left({})", d)),
+                    d => body.push(format!("right({})", d.unsigned_abs())),
+                }
+            }
+        }
+        self.push_loop(block, body);
+    }
+}
+
+/* fonction permettant de traduire du Brainfuck en BF-IL lisible
+    les idiomes (remise à 0, déplacement, copie, multiplication) sont reconnus par les passes de l'optimiseur au niveau O1
+*/
+pub fn decompile(code: &str) -> Result<String> {
+    let ist_list = PassManager::new(OptLevel::O1).run(parse_brainfuck(code)?);
+    let mut decompiler = Decompiler::default();
+    let mut block = Block::new(Some(0), false);
+    decompiler.decompile_block(&ist_list, &mut block);
+
+    let mut res = String::new();
+    for function in &decompiler.functions {
+        res.push_str(function);
+        res.push('\n');
+    }
+    for line in block.lines {
+        res.push_str(&line);
+        res.push('\n');
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtin::expand_builtin;
+    use crate::code_checker::{transform_goto, Outputpointer};
+    use crate::function::{produce_mapping, replace_function};
+    use crate::instruction::Instruction;
+    use crate::interpreter;
+    use crate::layout::produce_layout;
+    use crate::producer::produce;
+    use std::collections::HashSet;
+
+    fn compile(source: &str) -> String {
+        let (ist, names) = Instruction::parse(source.to_owned());
+        let (ist, mapping) = produce_mapping(ist.unwrap(), names).unwrap();
+        let (ist, layout) = produce_layout(ist).unwrap();
+        let ist = replace_function(&ist, &mapping, &mut HashSet::new(), &layout).unwrap();
+        let ist = expand_builtin(ist, &layout).unwrap();
+        let (ist, _) = transform_goto(ist, Outputpointer::default());
+        produce(ist.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn decompile_idioms() {
+        assert_eq!(
+            decompile(">+++[->++<]>[-]<<,[>.<-]>[>]").unwrap(),
+            "add(1, 3)\n// multiply to 2\nloop {\n    sub(1, 1)\n    add(2, 2)\n    goto(1)\n}\n\
+             setconst(2, 0)\ngoto(0)\nread()\nloop {\n    goto(1)\n    print()\n    sub(0, 1)\n}\n\
+             goto(1)\nloop {\n    right(1)\n}\n"
+        );
+    }
+
+    #[test]
+    fn decompiled_code_behaves_the_same() {
+        let code = ",>,<[->+>+<<]>>[-<<+>>]<[->>+<<]+++[>+++++<-]>[<++>-]<.>>.<<<[.[-]>]";
+        let source = decompile(code).unwrap();
+        let input = b"\x07\x05";
+        assert_eq!(
+            interpreter::run(&compile(&source), input).unwrap().output,
+            interpreter::run(code, input).unwrap().output
+        );
+    }
+}
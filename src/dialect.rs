@@ -0,0 +1,210 @@
+use anyhow::{anyhow, Result};
+
+use crate::producer::{format_with, Format, COMMANDS, INDENT};
+
+/* mots remplaçant chaque commande, dans l'ordre de COMMANDS */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenMap {
+    tokens: Vec<String>,
+}
+
+impl TokenMap {
+    fn new(tokens: [&str; 8]) -> Self {
+        Self {
+            tokens: tokens.iter().map(|token| token.to_string()).collect(),
+        }
+    }
+
+    pub fn ook() -> Self {
+        Self::new([
+            "Ook. Ook.",
+            "Ook! Ook!",
+            "Ook? Ook.",
+            "Ook. Ook?",
+            "Ook! Ook?",
+            "Ook? Ook!",
+            "Ook! Ook.",
+            "Ook. Ook!",
+        ])
+    }
+
+    /* le Blub est le Ook! dont on a changé le mot */
+    pub fn blub() -> Self {
+        Self {
+            tokens: Self::ook()
+                .tokens
+                .iter()
+                .map(|token| token.replace("Ook", "Blub"))
+                .collect(),
+        }
+    }
+
+    /* fonction permettant de lire une table fournie par l'utilisateur: une ligne par commande, la commande puis son mot
+        les lignes vides sont ignorées et chacune des huit commandes doit recevoir un mot
+    */
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut tokens = vec![None; COMMANDS.len()];
+        for line in source
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            let mut chars = line.chars();
+            let command = chars.next().unwrap();
+            let token = chars.as_str().trim();
+            let index = COMMANDS
+                .find(command)
+                .ok_or_else(|| anyhow!("unknown command {} in token map", command))?;
+            if token.is_empty() {
+                return Err(anyhow!("missing token for command {}", command));
+            }
+            if tokens[index].replace(token.to_owned()).is_some() {
+                return Err(anyhow!("command {} is mapped twice", command));
+            }
+        }
+        Ok(Self {
+            tokens: tokens
+                .into_iter()
+                .zip(COMMANDS.chars())
+                .map(|(token, command)| {
+                    token.ok_or_else(|| anyhow!("no token for command {}", command))
+                })
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+
+    /* fonction permettant de traduire le Brainfuck, les mots étant séparés par des espaces
+        les lignes sont coupées à width caractères sauf en mode Minified, les commentaires sont retirés car ils pourraient contenir un mot
+        en mode Pretty chaque crochet est seul sur sa ligne et le contenu des boucles est indenté, comme pour le Brainfuck
+    */
+    pub fn translate(&self, code: &str, format: Format, width: usize) -> String {
+        let mut res = String::new();
+        let mut line = String::new();
+        let mut depth = 0usize;
+        let flush = |res: &mut String, line: &mut String, depth: usize| {
+            if !line.is_empty() {
+                res.push_str(&" ".repeat(depth * INDENT));
+                res.push_str(line);
+                res.push('\n');
+                line.clear();
+            }
+        };
+        for c in code.chars() {
+            let Some(index) = COMMANDS.find(c) else {
+                continue;
+            };
+            let token = &self.tokens[index];
+            if format == Format::Pretty && (c == '[' || c == ']') {
+                flush(&mut res, &mut line, depth);
+                if c == ']' {
+                    depth = depth.saturating_sub(1);
+                }
+                line.push_str(token);
+                flush(&mut res, &mut line, depth);
+                if c == '[' {
+                    depth += 1;
+                }
+                continue;
+            }
+            let level = if format == Format::Pretty { depth } else { 0 };
+            if !line.is_empty() {
+                if format != Format::Minified
+                    && level * INDENT + line.len() + 1 + token.len() > width
+                {
+                    flush(&mut res, &mut line, level);
+                } else {
+                    line.push(' ');
+                }
+            }
+            line.push_str(token);
+        }
+        flush(&mut res, &mut line, depth);
+        if res.is_empty() {
+            res.push('\n');
+        }
+        res
+    }
+}
+
+/* langage dans lequel on écrit le Brainfuck produit
+    Debug est le Brainfuck avec la commande # qui affiche l'état de la machine, placée avant chaque ligne du source
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dialect {
+    Brainfuck,
+    Debug,
+    Tokens(TokenMap),
+}
+
+impl Dialect {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "bf" => Ok(Self::Brainfuck),
+            "debug" => Ok(Self::Debug),
+            "ook" => Ok(Self::Tokens(TokenMap::ook())),
+            "blub" => Ok(Self::Tokens(TokenMap::blub())),
+            _ => Err(anyhow!("unknown dialect {}", name)),
+        }
+    }
+
+    /* le dialecte Debug a besoin des annotations pour savoir où placer ses # */
+    #[inline]
+    pub const fn needs_annotations(&self) -> bool {
+        matches!(self, Self::Debug)
+    }
+
+    /* fonction permettant de mettre en forme le Brainfuck produit par produce dans ce dialecte */
+    pub fn render(&self, code: &str, format: Format, width: usize) -> String {
+        match self {
+            Self::Brainfuck => format_with(code, format, width, COMMANDS),
+            Self::Debug => {
+                let mut res = String::new();
+                for line in code.lines() {
+                    let comment =
+                        !line.trim().is_empty() && !line.chars().any(|c| COMMANDS.contains(c));
+                    if comment {
+                        res.push_str("#\n");
+                    }
+                    res.push_str(line);
+                    res.push('\n');
+                }
+                format_with(&res, format, width, &format!("{}#", COMMANDS))
+            }
+            Self::Tokens(map) => map.translate(code, format, width),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dialects() {
+        let code = "+[-\n]\nline 2 at cell 0\n.";
+        assert_eq!(
+            Dialect::parse("ook").unwrap().render(code, Format::Raw, 25),
+            "Ook. Ook. Ook! Ook?\nOok! Ook! Ook? Ook!\nOok! Ook.\n"
+        );
+        assert_eq!(
+            Dialect::parse("blub")
+                .unwrap()
+                .render(code, Format::Minified, 10),
+            "Blub. Blub. Blub! Blub? Blub! Blub! Blub? Blub! Blub! Blub.\n"
+        );
+        assert_eq!(Dialect::Debug.render(code, Format::Minified, 80), "+[-]#.");
+        let map = TokenMap::parse("+ inc\n- dec\n< left\n> right\n\n[ do\n] done\n. put\n, get\n")
+            .unwrap();
+        assert_eq!(
+            map.translate(code, Format::Raw, 80),
+            "inc do dec done put\n"
+        );
+        assert_eq!(
+            map.translate("+[-[>]]+", Format::Pretty, 80),
+            "inc\ndo\n    dec\n    do\n        right\n    done\ndone\ninc\n"
+        );
+        assert!(TokenMap::parse("+ inc\n- dec").is_err());
+        assert!(TokenMap::parse("+ inc\n+ plus").is_err());
+        assert!(TokenMap::parse("x ex").is_err());
+    }
+}
@@ -1,16 +1,20 @@
 use std::collections::{HashMap, HashSet};
-use std::ops::ControlFlow;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
 
+use crate::builtin::Builtin;
+use crate::context::Context;
 use crate::instruction::Instruction;
+use crate::layout::Layout;
 use crate::variable::Variable;
 
 /* après que l'on remplace les fonctions, on n'a plus besoins de certain champs, donc on utilise une nouvelle structure */
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum FlattenedInstruction {
     Noop,
     Print,
+    Read,
     Add(usize),
     Sub(usize),
     SetConst(usize),
@@ -18,6 +22,13 @@ pub enum FlattenedInstruction {
     Left(usize),
     Right(usize),
     Loop(Vec<FlattenedInstruction>),
+    Builtin(Builtin, Vec<usize>),
+    Sync(usize), //la tête de lecture se trouve de nouveau sur cette cellule
+    Clear,       //[-]
+    MulAdd { offset: isize, factor: isize }, //ajoute factor fois la cellule courante à celle située offset plus loin, toujours suivi d'un Clear
+    ScanLeft(usize),                         //[<] en avançant de plusieurs cellules à la fois
+    ScanRight(usize),                        //[>]
+    Annotation(Context),                     //commentaire indiquant l'origine du code qui suit
 }
 
 impl TryFrom<Instruction> for FlattenedInstruction {
@@ -27,6 +38,7 @@ impl TryFrom<Instruction> for FlattenedInstruction {
         match value {
             Instruction::Noop => Ok(FlattenedInstruction::Noop),
             Instruction::Print => Ok(FlattenedInstruction::Print),
+            Instruction::Read => Ok(FlattenedInstruction::Read),
             Instruction::Reset => Ok(Self::Loop(vec![Self::Sub(1)])),
             Instruction::Add(v) => Ok(Self::Add(v.unwrap_value())),
             Instruction::Sub(v) => Ok(Self::Sub(v.unwrap_value())),
@@ -37,16 +49,20 @@ impl TryFrom<Instruction> for FlattenedInstruction {
             Instruction::Loop(inner) => Ok(Self::Loop(
                 inner
                     .into_iter()
-                    .map(|ist| Self::try_from(ist))
+                    .map(Self::try_from)
                     .collect::<Result<Vec<_>>>()?,
             )),
+            Instruction::Builtin(builtin, args) => Ok(Self::Builtin(
+                builtin,
+                args.iter().map(Variable::unwrap_value).collect(),
+            )),
             _ => unreachable!(),
         }
     }
 }
 
 /* structure permettant de garder en mémoire les signatures des fonctions */
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct FnSignature {
     arg_state: Vec<String>,
 }
@@ -59,15 +75,18 @@ impl From<Vec<String>> for FnSignature {
 
 impl FnSignature {
     /* fonction permettant de produire un mapping à partir des valeurs de chaque argument */
-    fn produce_args_mapping(&self, args: Vec<Variable>) -> HashMap<String, Variable> {
+    pub fn produce_args_mapping(&self, args: Vec<Variable>) -> HashMap<String, Variable> {
         self.arg_state
             .iter()
-            .zip(args.into_iter())
+            .zip(args)
             .map(|(name, value)| (name.to_owned(), value))
             .collect::<HashMap<_, _>>()
     }
 }
 
+/* table associant à chaque nom de fonction sa signature et son contenue */
+pub type FunctionMapping = HashMap<String, (FnSignature, Vec<Instruction>)>;
+
 /* fontion permettant de faire deux chose:
     1) vérifier que l'on appele pas des fonction non déclarés
     2) sépare les fonctions du reste des instructions
@@ -75,10 +94,7 @@ impl FnSignature {
 pub fn produce_mapping(
     ist_list: Vec<Instruction>,
     namespace: HashSet<String>,
-) -> Result<(
-    Vec<Instruction>,
-    HashMap<String, (FnSignature, Vec<Instruction>)>,
-)> {
+) -> Result<(Vec<Instruction>, FunctionMapping)> {
     let mut error_calling = String::default();
 
     let (fn_list, ist_without_list): (Vec<_>, Vec<_>) = ist_list
@@ -94,13 +110,10 @@ pub fn produce_mapping(
         .collect::<HashMap<_, _>>();
 
     ist_without_list.iter().for_each(|ist| {
-        match ist {
-            Instruction::FnCall(name, _) => {
-                if !namespace.contains(name) {
-                    error_calling = name.to_owned();
-                }
+        if let Instruction::FnCall(name, _) = ist {
+            if !namespace.contains(name) {
+                error_calling = name.to_owned();
             }
-            _ => (),
         };
     });
 
@@ -117,7 +130,7 @@ pub fn produce_mapping(
 /* fonction permettant de subsituter toute les varibles littérale en leurs valeurs dans une appels de fonction
 */
 pub fn substitute(
-    ist_list: &Vec<Instruction>,
+    ist_list: &[Instruction],
     substitution: &HashMap<String, Variable>,
 ) -> Result<Vec<Instruction>> {
     ist_list
@@ -125,7 +138,9 @@ pub fn substitute(
         .map(|ist| match ist {
             Instruction::Noop => Ok(Instruction::Noop),
             Instruction::Print => Ok(Instruction::Print),
+            Instruction::Read => Ok(Instruction::Read),
             Instruction::Reset => Ok(Instruction::Reset),
+            Instruction::Line(context) => Ok(Instruction::Line(context.clone())),
             Instruction::Add(var) => Ok(Instruction::Add(var.try_substitute(substitution)?)),
             Instruction::Sub(var) => Ok(Instruction::Sub(var.try_substitute(substitution)?)),
             Instruction::Left(var) => Ok(Instruction::Left(var.try_substitute(substitution)?)),
@@ -134,28 +149,39 @@ pub fn substitute(
                 Ok(Instruction::SetConst(var.try_substitute(substitution)?))
             }
             Instruction::Goto(var) => Ok(Instruction::Goto(var.try_substitute(substitution)?)),
-            Instruction::Loop(inner) => Ok(Instruction::Loop(substitute(&inner, substitution)?)),
+            Instruction::Loop(inner) => Ok(Instruction::Loop(substitute(inner, substitution)?)),
             Instruction::FnCall(name, args) => Ok(Instruction::FnCall(
                 name.to_owned(),
-                args.into_iter()
+                args.iter()
                     .map(|arg| arg.try_substitute(substitution))
                     .collect::<Result<Vec<_>>>()?,
             )),
-            Instruction::Fn(_, _, _) => unreachable!(),
+            Instruction::Builtin(builtin, args) => Ok(Instruction::Builtin(
+                *builtin,
+                args.iter()
+                    .map(|arg| arg.try_substitute(substitution))
+                    .collect::<Result<Vec<_>>>()?,
+            )),
+            Instruction::Fn(_, _, _)
+            | Instruction::Var(_, _)
+            | Instruction::Array(_, _)
+            | Instruction::Stack(_) => unreachable!(),
         })
         .collect::<Result<Vec<_>>>()
 }
 
 /* fonction permettant de subsituter tout les appels de fonction en leurs contenue, ici on interdit la récursivité car les ce que l'on appele ici fonction
     n'est qu'ne soit des macros
+    les variables déclarées sont remplacées par leurs adresses grâce au layout
 */
 pub fn replace_function(
-    ist_list: &Vec<Instruction>,
-    mapping: &HashMap<String, (FnSignature, Vec<Instruction>)>,
+    ist_list: &[Instruction],
+    mapping: &FunctionMapping,
     context: &mut HashSet<String>,
+    layout: &Layout,
 ) -> Result<Vec<FlattenedInstruction>> {
     Ok(ist_list
-        .into_iter()
+        .iter()
         .map(|ist| {
             match ist {
                 Instruction::FnCall(fn_name, args) => {
@@ -171,30 +197,47 @@ pub fn replace_function(
 
                         let res = substitute(fun_ist_list, &substitution)?;
                         context.insert(fn_name.to_owned());
-                        replace_function(&res, mapping, context)
+                        let mut res = replace_function(&res, mapping, context, layout)?;
+                        for_each_annotation(&mut res, &mut |annotation| {
+                            annotation.calls.push(fn_name.to_owned())
+                        });
+                        Ok(res)
                     }
                 }
                 Instruction::Noop => Ok(vec![FlattenedInstruction::Noop]),
                 Instruction::Print => Ok(vec![FlattenedInstruction::Print]),
+                Instruction::Read => Ok(vec![FlattenedInstruction::Read]),
+                Instruction::Line(context) => {
+                    Ok(vec![FlattenedInstruction::Annotation(context.clone())])
+                }
                 Instruction::Reset => Ok(vec![FlattenedInstruction::Loop(vec![
                     FlattenedInstruction::Sub(1),
                 ])]),
-                Instruction::Add(var) => Ok(vec![FlattenedInstruction::Add(var.unwrap_value())]),
-                Instruction::Sub(var) => Ok(vec![FlattenedInstruction::Sub(var.unwrap_value())]),
-                Instruction::Left(var) => Ok(vec![FlattenedInstruction::Left(var.unwrap_value())]),
-                Instruction::Right(var) => {
-                    Ok(vec![FlattenedInstruction::Right(var.unwrap_value())])
-                }
+                Instruction::Add(var) => Ok(vec![FlattenedInstruction::Add(var.amount()?)]),
+                Instruction::Sub(var) => Ok(vec![FlattenedInstruction::Sub(var.amount()?)]),
+                Instruction::Left(var) => Ok(vec![FlattenedInstruction::Left(var.amount()?)]),
+                Instruction::Right(var) => Ok(vec![FlattenedInstruction::Right(var.amount()?)]),
                 Instruction::SetConst(var) => {
-                    Ok(vec![FlattenedInstruction::SetConst(var.unwrap_value())])
+                    Ok(vec![FlattenedInstruction::SetConst(var.amount()?)])
+                }
+                Instruction::Goto(var) => {
+                    Ok(vec![FlattenedInstruction::Goto(layout.resolve(var)?)])
                 }
-                Instruction::Goto(var) => Ok(vec![FlattenedInstruction::Goto(var.unwrap_value())]),
 
                 Instruction::Loop(inner) => Ok(vec![FlattenedInstruction::Loop(replace_function(
-                    inner, mapping, context,
+                    inner, mapping, context, layout,
                 )?)]),
+                Instruction::Builtin(builtin, args) => Ok(vec![FlattenedInstruction::Builtin(
+                    *builtin,
+                    args.iter()
+                        .map(|arg| layout.resolve(arg))
+                        .collect::<Result<Vec<_>>>()?,
+                )]),
 
-                Instruction::Fn(_, _, _) => unreachable!(),
+                Instruction::Fn(_, _, _)
+                | Instruction::Var(_, _)
+                | Instruction::Array(_, _)
+                | Instruction::Stack(_) => unreachable!(),
             }
         })
         .collect::<Result<Vec<_>>>()?
@@ -202,3 +245,64 @@ pub fn replace_function(
         .flatten()
         .collect())
 }
+
+/* fonction permettant d'appliquer une modification à toutes les annotations, y compris dans les boucles */
+fn for_each_annotation(ist_list: &mut [FlattenedInstruction], f: &mut impl FnMut(&mut Context)) {
+    for ist in ist_list {
+        match ist {
+            FlattenedInstruction::Annotation(context) => f(context),
+            FlattenedInstruction::Loop(inner) => for_each_annotation(inner, f),
+            _ => (),
+        }
+    }
+}
+
+/* fonction permettant de retirer les annotations quand on ne veut pas de commentaires, elles gêneraient les optimisations */
+pub fn strip_annotations(ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+    ist_list
+        .into_iter()
+        .filter_map(|ist| match ist {
+            FlattenedInstruction::Annotation(_) => None,
+            FlattenedInstruction::Loop(inner) => {
+                Some(FlattenedInstruction::Loop(strip_annotations(inner)))
+            }
+            ist => Some(ist),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::produce_layout;
+
+    fn flatten(source: &str) -> Result<Vec<FlattenedInstruction>> {
+        let (ist, names) = Instruction::parse(source.to_owned());
+        let (ist, mapping) = produce_mapping(ist?, names)?;
+        let (ist, layout) = produce_layout(ist)?;
+        replace_function(&ist, &mapping, &mut HashSet::new(), &layout)
+    }
+
+    #[test]
+    fn amounts_must_be_constants() {
+        assert!(flatten("var x; var a; goto(a) add(x)").is_err());
+        assert!(flatten("var x; right(x)").is_err());
+        assert_eq!(
+            strip_annotations(flatten("var x; var a; goto(a) add(2)").unwrap()),
+            vec![
+                FlattenedInstruction::Goto(1),
+                FlattenedInstruction::Add(2),
+                FlattenedInstruction::Noop
+            ]
+        );
+    }
+
+    #[test]
+    fn builtin_names_are_reserved() {
+        for name in ["mul", "PrintNum", "load", "Push", "isempty", "add", "Print"] {
+            let source = format!("var a;\nfn {}(x) {{\n    print()\n}}\n", name);
+            assert!(flatten(&source).is_err(), "{} was accepted", name);
+        }
+        assert!(flatten("var a;\nfn twice(x) {\n    print()\n    print()\n}\ntwice(a)\n").is_ok());
+    }
+}
@@ -2,11 +2,14 @@ use std::collections::HashSet;
 
 use anyhow::{anyhow, Context, Result};
 use pest::Parser;
+use serde::{Deserialize, Serialize};
 extern crate pest;
 use pest::iterators::Pair;
 
+use crate::builtin::Builtin;
+use crate::context;
 use crate::function::FnSignature;
-use crate::optimizer::InstructionKind;
+use crate::layout::VarType;
 use crate::variable::Variable;
 use crate::STD_FUNCTION;
 
@@ -14,10 +17,11 @@ use crate::STD_FUNCTION;
 #[grammar = "bf_il.pest"]
 struct BFILParser;
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum Instruction {
     Noop,
     Print,
+    Read,
     Add(Variable),
     Sub(Variable),
     SetConst(Variable),
@@ -26,20 +30,39 @@ pub enum Instruction {
     Right(Variable),
     Reset,
     FnCall(String, Vec<Variable>), //first: name function second: arg
+    Builtin(Builtin, Vec<Variable>),
     Fn(String, FnSignature, Vec<Instruction>), //nom, contenue
+    Var(String, VarType),
+    Array(String, usize),
+    Stack(String),
     Loop(Vec<Instruction>),
+    Line(context::Context), //marque le début d'une ligne du source, pour annoter le code produit
 }
 
 impl Instruction {
+    /* fonction permettant de parser une suite d'instructions, chaque instruction ou boucle étant précédée de sa ligne dans le source */
+    fn from_list<'a>(
+        source: impl Iterator<Item = Pair<'a, Rule>>,
+        function_namespace: &mut HashSet<String>,
+    ) -> Result<Vec<Self>> {
+        let mut res = Vec::new();
+        for ist in source {
+            if matches!(ist.as_rule(), Rule::Instruction | Rule::Loop) {
+                let (line, _) = ist.as_span().start_pos().line_col();
+                res.push(Self::Line(context::Context::new(line)));
+            }
+            res.push(Self::from(ist, function_namespace)?);
+        }
+        Ok(res)
+    }
+
     /* fonction permettant de parser les instctions */
     fn from(source: Pair<Rule>, function_namespace: &mut HashSet<String>) -> Result<Self> {
         match source.as_rule() {
             Rule::Loop => {
                 let ist_inner = source.into_inner();
                 Ok(Self::Loop(
-                    ist_inner
-                        .map(|ist| Self::from(ist, function_namespace))
-                        .collect::<Result<Vec<_>>>()
+                    Self::from_list(ist_inner, function_namespace)
                         .context("failed to parse function instruction")?,
                 ))
             }
@@ -51,44 +74,75 @@ impl Instruction {
                     .context(format!("failed to parse function name on {:?}", ist_inner))?
                     .as_str();
 
-                if function_namespace.contains(fucname) || STD_FUNCTION.contains(&fucname) {
+                //les appels étant mis en minuscules, une fonction ne doit masquer aucune fonction standard quelle que soit sa casse
+                if function_namespace.contains(fucname)
+                    || STD_FUNCTION
+                        .iter()
+                        .any(|name| name.eq_ignore_ascii_case(fucname))
+                    || Builtin::from_name(&fucname.to_lowercase()).is_some()
+                {
                     Err(anyhow!(
                         "try to redeclare function with is already declared"
                     ))
                 } else {
-                    let funcarg = ist_inner
-                        .next()
-                        .context(format!(
-                            "failed to parse function argument on {:?}",
-                            ist_inner
-                        ))?
-                        .into_inner()
-                        .map(|arg| match arg.as_rule() {
-                            Rule::name => Ok(arg.as_str().to_owned()),
-                            _ => Err(anyhow!("pass an non-variable pair into variable parser")),
-                        })
-                        .collect::<Result<Vec<_>>>()?;
+                    //une fonction sans argument n'a pas de paire args
+                    let funcarg = match ist_inner.peek().map(|pair| pair.as_rule()) {
+                        Some(Rule::args) => ist_inner
+                            .next()
+                            .unwrap()
+                            .into_inner()
+                            .map(|arg| match arg.as_rule() {
+                                Rule::name => Ok(arg.as_str().to_owned()),
+                                _ => Err(anyhow!("pass an non-variable pair into variable parser")),
+                            })
+                            .collect::<Result<Vec<_>>>()?,
+                        _ => Vec::new(),
+                    };
 
                     function_namespace.insert(fucname.to_string());
 
                     let signature = FnSignature::from(funcarg);
 
-                    let funcist = ist_inner
-                        .map(|ist| Self::from(ist, function_namespace))
-                        .collect::<Result<Vec<_>>>()
+                    let funcist = Self::from_list(ist_inner, function_namespace)
                         .context("failed to parse function instruction")?;
 
                     Ok(Self::Fn(fucname.to_string(), signature, funcist))
                 }
             }
-            Rule::Loop => {
+            Rule::Var => {
                 let mut ist_inner = source.into_inner();
-                let loopist = ist_inner
-                    .map(|ist| Self::from(ist, function_namespace))
-                    .collect::<Result<Vec<_>>>()
-                    .context("failed to parse function instruction")?;
+                let varname = ist_inner
+                    .next()
+                    .context(format!("failed to parse variable name on {:?}", ist_inner))?
+                    .as_str();
+                let vartype = match ist_inner.next() {
+                    Some(ty) => VarType::parse(ty.as_str())?,
+                    None => VarType::U8,
+                };
+                Ok(Self::Var(varname.to_string(), vartype))
+            }
 
-                Ok(Self::Loop(loopist))
+            Rule::Array => {
+                let mut ist_inner = source.into_inner();
+                let arrayname = ist_inner
+                    .next()
+                    .context(format!("failed to parse array name on {:?}", ist_inner))?
+                    .as_str();
+                let len = ist_inner
+                    .next()
+                    .context(format!("failed to parse array length on {:?}", ist_inner))?
+                    .as_str()
+                    .parse()?;
+                Ok(Self::Array(arrayname.to_string(), len))
+            }
+
+            Rule::Stack => {
+                let mut ist_inner = source.into_inner();
+                let stackname = ist_inner
+                    .next()
+                    .context(format!("failed to parse stack name on {:?}", ist_inner))?
+                    .as_str();
+                Ok(Self::Stack(stackname.to_string()))
             }
 
             Rule::Instruction => {
@@ -121,9 +175,11 @@ impl Instruction {
                         if var.len() == 1 {
                             let ist_var = var.pop().unwrap();
                             Ok(Instruction::Add(ist_var))
+                        } else if var.len() == 2 {
+                            Ok(Instruction::Builtin(Builtin::Add, var))
                         } else {
                             Err(anyhow!(format!(
-                                "invalid argument in add call, expected one, get {}",
+                                "invalid argument in add call, expected one or two, get {}",
                                 var.len()
                             )))
                         }
@@ -135,9 +191,11 @@ impl Instruction {
                         if var.len() == 1 {
                             let ist_var = var.pop().unwrap();
                             Ok(Instruction::Sub(ist_var))
+                        } else if var.len() == 2 {
+                            Ok(Instruction::Builtin(Builtin::Sub, var))
                         } else {
                             Err(anyhow!(format!(
-                                "invalid argument in Sub call, expected one, get {}",
+                                "invalid argument in Sub call, expected one or two, get {}",
                                 var.len()
                             )))
                         }
@@ -177,9 +235,11 @@ impl Instruction {
                         if var.len() == 1 {
                             let ist_var = var.pop().unwrap();
                             Ok(Instruction::SetConst(ist_var))
+                        } else if var.len() == 2 {
+                            Ok(Instruction::Builtin(Builtin::SetConst, var))
                         } else {
                             Err(anyhow!(format!(
-                                "invalid argument in SetConst call, expected one, get {}",
+                                "invalid argument in SetConst call, expected one or two, get {}",
                                 var.len()
                             )))
                         }
@@ -193,6 +253,15 @@ impl Instruction {
                             )))
                         }
                     }
+                    "read" => {
+                        if ist_inner.peek().is_none() {
+                            Ok(Instruction::Read)
+                        } else {
+                            Err(anyhow!(format!(
+                                "invalid argument in Read call, expected 0",
+                            )))
+                        }
+                    }
                     "erbset" => {
                         if ist_inner.peek().is_none() {
                             Ok(Instruction::Reset)
@@ -202,10 +271,30 @@ impl Instruction {
                             )))
                         }
                     }
-                    name => Ok(Instruction::FnCall(
-                        name.to_string(),
-                        Variable::parse_vec(ist_inner.next().context("failed to parse argument")?),
-                    )),
+                    name => match Builtin::from_name(name) {
+                        Some(builtin) => {
+                            let var = Variable::parse_vec(
+                                ist_inner.next().context("failed to parse argument")?,
+                            );
+                            if var.len() == builtin.arity() {
+                                Ok(Instruction::Builtin(builtin, var))
+                            } else {
+                                Err(anyhow!(format!(
+                                    "invalid argument in {} call, expected {}, get {}",
+                                    name,
+                                    builtin.arity(),
+                                    var.len()
+                                )))
+                            }
+                        }
+                        None => Ok(Instruction::FnCall(
+                            name.to_string(),
+                            ist_inner
+                                .next()
+                                .map(Variable::parse_vec)
+                                .unwrap_or_default(),
+                        )),
+                    },
                 }
             }
 
@@ -217,23 +306,15 @@ impl Instruction {
         let mut function_namespace = HashSet::<String>::new();
 
         (
-            BFILParser::parse(Rule::File, &source)
-                .unwrap()
-                .next()
-                .unwrap()
-                .into_inner()
-                .map(|ist| Instruction::from(ist, &mut function_namespace))
-                .collect::<Result<Vec<_>>>(),
+            Self::from_list(
+                BFILParser::parse(Rule::File, &source)
+                    .unwrap()
+                    .next()
+                    .unwrap()
+                    .into_inner(),
+                &mut function_namespace,
+            ),
             function_namespace,
         )
     }
-
-    #[inline]
-    pub const fn get_type(&self) -> InstructionKind {
-        match self {
-            Self::Add(_) | Self::Sub(_) | Self::Left(_) | Self::Right(_) => InstructionKind::Linear,
-            Self::Noop | Self::SetConst(_) | Self::Goto(_) => InstructionKind::Replaceable,
-            _ => InstructionKind::Any,
-        }
-    }
 }
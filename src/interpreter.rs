@@ -0,0 +1,72 @@
+use anyhow::{anyhow, Result};
+
+/* état de la machine Brainfuck à la fin de l'exécution */
+#[derive(Debug)]
+pub struct Machine {
+    pub tape: Vec<u8>,
+    pub pointer: usize,
+    pub output: Vec<u8>,
+}
+
+/* fonction permettant d'associer chaque crochet à son crochet correspondant */
+fn match_brackets(code: &[u8]) -> Result<Vec<usize>> {
+    let mut jumps = vec![0; code.len()];
+    let mut stack = Vec::new();
+    for (i, c) in code.iter().enumerate() {
+        match c {
+            b'[' => stack.push(i),
+            b']' => {
+                let open = stack
+                    .pop()
+                    .ok_or_else(|| anyhow!("unmatched ']' at {}", i))?;
+                jumps[open] = i;
+                jumps[i] = open;
+            }
+            _ => (),
+        }
+    }
+    match stack.pop() {
+        Some(open) => Err(anyhow!("unmatched '[' at {}", open)),
+        None => Ok(jumps),
+    }
+}
+
+/* interpréteur naïf caractère par caractère, cellules de 8 bits, la fin de l'entrée donne 0 */
+pub fn run(code: &str, input: &[u8]) -> Result<Machine> {
+    let code = code.as_bytes();
+    let jumps = match_brackets(code)?;
+    let mut machine = Machine {
+        tape: vec![0; 30000],
+        pointer: 0,
+        output: Vec::new(),
+    };
+    let mut input = input.iter();
+    let mut pc = 0;
+
+    while pc < code.len() {
+        match code[pc] {
+            b'+' => machine.tape[machine.pointer] = machine.tape[machine.pointer].wrapping_add(1),
+            b'-' => machine.tape[machine.pointer] = machine.tape[machine.pointer].wrapping_sub(1),
+            b'>' => {
+                machine.pointer += 1;
+                if machine.pointer == machine.tape.len() {
+                    machine.tape.push(0);
+                }
+            }
+            b'<' => {
+                machine.pointer = machine
+                    .pointer
+                    .checked_sub(1)
+                    .ok_or_else(|| anyhow!("move left of the first cell at {}", pc))?
+            }
+            b'.' => machine.output.push(machine.tape[machine.pointer]),
+            b',' => machine.tape[machine.pointer] = input.next().copied().unwrap_or(0),
+            b'[' if machine.tape[machine.pointer] == 0 => pc = jumps[pc],
+            b']' if machine.tape[machine.pointer] != 0 => pc = jumps[pc],
+            _ => (),
+        }
+        pc += 1;
+    }
+
+    Ok(machine)
+}
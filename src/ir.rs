@@ -0,0 +1,343 @@
+use anyhow::{anyhow, Context as _, Result};
+
+use crate::backend::push_line;
+use crate::builtin::{check_cell_type, expand_builtin, Builtin};
+use crate::code_checker::{transform_goto, Outputpointer};
+use crate::context::Context;
+use crate::function::FlattenedInstruction;
+use crate::layout::{Layout, VarType};
+
+/* position de la tête de lecture après une instruction, None quand elle ne peut pas être connue sans exécuter le programme
+    une boucle ne la conserve que si son corps revient sur la cellule testée
+*/
+pub(crate) fn step(ist: &FlattenedInstruction, position: Option<isize>) -> Option<isize> {
+    match ist {
+        FlattenedInstruction::Goto(cell) | FlattenedInstruction::Sync(cell) => Some(*cell as isize),
+        FlattenedInstruction::Left(n) => position.map(|p| p - *n as isize),
+        FlattenedInstruction::Right(n) => position.map(|p| p + *n as isize),
+        FlattenedInstruction::ScanLeft(_)
+        | FlattenedInstruction::ScanRight(_)
+        | FlattenedInstruction::Builtin(_, _) => None,
+        FlattenedInstruction::Loop(inner) => {
+            let end = inner.iter().fold(position, |p, ist| step(ist, p));
+            position.filter(|_| end == position)
+        }
+        _ => position,
+    }
+}
+
+/* texte d'une instruction seule, les boucles étant ouvertes par loop { */
+fn describe(ist: &FlattenedInstruction) -> String {
+    match ist {
+        FlattenedInstruction::Noop => String::from("noop"),
+        FlattenedInstruction::Print => String::from("print"),
+        FlattenedInstruction::Read => String::from("read"),
+        FlattenedInstruction::Add(n) => format!("add {}", n),
+        FlattenedInstruction::Sub(n) => format!("sub {}", n),
+        FlattenedInstruction::SetConst(v) => format!("setconst {}", v),
+        FlattenedInstruction::Goto(cell) => format!("goto {}", cell),
+        FlattenedInstruction::Left(n) => format!("left {}", n),
+        FlattenedInstruction::Right(n) => format!("right {}", n),
+        FlattenedInstruction::Loop(_) => String::from("loop {"),
+        FlattenedInstruction::Builtin(builtin, args) => {
+            let mut res = format!("builtin {}", builtin.name());
+            for arg in args {
+                res += &format!(" {}", arg);
+            }
+            res
+        }
+        FlattenedInstruction::Sync(cell) => format!("sync {}", cell),
+        FlattenedInstruction::Clear => String::from("clear"),
+        FlattenedInstruction::MulAdd { offset, factor } => format!("muladd {} {}", offset, factor),
+        FlattenedInstruction::ScanLeft(stride) => format!("scanleft {}", stride),
+        FlattenedInstruction::ScanRight(stride) => format!("scanright {}", stride),
+        FlattenedInstruction::Annotation(context) => context.describe(),
+    }
+}
+
+fn dump_block(
+    ist_list: &[FlattenedInstruction],
+    depth: usize,
+    mut position: Option<isize>,
+    res: &mut String,
+) {
+    for ist in ist_list {
+        let line = describe(ist);
+        match position {
+            Some(p) if !matches!(ist, FlattenedInstruction::Annotation(_)) => {
+                push_line(res, depth, &format!("{} ; @{}", line, p))
+            }
+            _ => push_line(res, depth, &line),
+        }
+        let next = step(ist, position);
+        if let FlattenedInstruction::Loop(inner) = ist {
+            //sans position connue à chaque tour, le corps est écrit sans position
+            dump_block(inner, depth + 1, next, res);
+            push_line(res, depth, "}");
+        }
+        position = next;
+    }
+}
+
+/* fonction permettant d'écrire les instructions aplaties sous forme textuelle: une instruction par ligne, le corps des boucles indenté
+    entre loop { et }, chaque instruction étant suivie en commentaire de la cellule sur laquelle elle s'exécute quand elle est connue
+    la tête de lecture part de la cellule 0
+*/
+pub fn dump(ist_list: &[FlattenedInstruction]) -> String {
+    let mut res = String::new();
+    dump_block(ist_list, 0, Some(0), &mut res);
+    res
+}
+
+/* fonction permettant de lire le nombre suivant d'une ligne */
+fn number<T: std::str::FromStr>(words: &mut std::str::SplitWhitespace) -> Result<T> {
+    let word = words.next().ok_or_else(|| anyhow!("missing number"))?;
+    word.parse().map_err(|_| anyhow!("invalid number {}", word))
+}
+
+/* fonction permettant de relire une annotation écrite par Context::describe */
+fn parse_annotation(words: &mut std::str::SplitWhitespace) -> Result<Context> {
+    let mut context = Context::new(number(words)?);
+    loop {
+        match words.next() {
+            Some("in") => context.calls.push(
+                words
+                    .next()
+                    .ok_or_else(|| anyhow!("missing function name"))?
+                    .to_owned(),
+            ),
+            Some("at") => break,
+            _ => return Err(anyhow!("invalid annotation")),
+        }
+    }
+    match words.next() {
+        Some("cell") => context.position = Some(number(words)?),
+        Some("unknown") if words.next() == Some("cell") => (),
+        _ => return Err(anyhow!("invalid annotation position")),
+    }
+    Ok(context)
+}
+
+/* fonction permettant de lire une instruction sans son corps */
+fn parse_instruction(line: &str) -> Result<FlattenedInstruction> {
+    let mut words = line.split_whitespace();
+    let ist = match words.next().unwrap() {
+        "noop" => FlattenedInstruction::Noop,
+        "print" => FlattenedInstruction::Print,
+        "read" => FlattenedInstruction::Read,
+        "add" => FlattenedInstruction::Add(number(&mut words)?),
+        "sub" => FlattenedInstruction::Sub(number(&mut words)?),
+        "setconst" => FlattenedInstruction::SetConst(number(&mut words)?),
+        "goto" => FlattenedInstruction::Goto(number(&mut words)?),
+        "left" => FlattenedInstruction::Left(number(&mut words)?),
+        "right" => FlattenedInstruction::Right(number(&mut words)?),
+        "sync" => FlattenedInstruction::Sync(number(&mut words)?),
+        "clear" => FlattenedInstruction::Clear,
+        "muladd" => FlattenedInstruction::MulAdd {
+            offset: number(&mut words)?,
+            factor: number(&mut words)?,
+        },
+        "scanleft" => FlattenedInstruction::ScanLeft(number(&mut words)?),
+        "scanright" => FlattenedInstruction::ScanRight(number(&mut words)?),
+        "builtin" => {
+            let name = words
+                .next()
+                .ok_or_else(|| anyhow!("missing builtin name"))?;
+            let builtin = Builtin::ALL
+                .into_iter()
+                .find(|builtin| builtin.name() == name)
+                .ok_or_else(|| anyhow!("unknown builtin {}", name))?;
+            let args = words
+                .by_ref()
+                .map(|arg| arg.parse().map_err(|_| anyhow!("invalid number {}", arg)))
+                .collect::<Result<Vec<_>>>()?;
+            if args.len() != builtin.arity() {
+                return Err(anyhow!("{} takes {} arguments", name, builtin.arity()));
+            }
+            FlattenedInstruction::Builtin(builtin, args)
+        }
+        "line" => FlattenedInstruction::Annotation(parse_annotation(&mut words)?),
+        word => return Err(anyhow!("unknown instruction {}", word)),
+    };
+    match words.next() {
+        Some(word) => Err(anyhow!("unexpected {}", word)),
+        None => Ok(ist),
+    }
+}
+
+/* fonction permettant de relire le texte produit par dump, les commentaires commençant par ; et l'indentation étant ignorés */
+pub fn parse(source: &str) -> Result<Vec<FlattenedInstruction>> {
+    let mut blocks = vec![Vec::new()];
+    for (number, line) in source.lines().enumerate() {
+        let line = line.split(';').next().unwrap().trim();
+        let ist = match line {
+            "" => continue,
+            "loop {" => {
+                blocks.push(Vec::new());
+                continue;
+            }
+            "}" if blocks.len() > 1 => FlattenedInstruction::Loop(blocks.pop().unwrap()),
+            "}" => return Err(anyhow!("unmatched }} on line {}", number + 1)),
+            _ => parse_instruction(line).with_context(|| format!("on line {}", number + 1))?,
+        };
+        blocks.last_mut().unwrap().push(ist);
+    }
+    match blocks.len() {
+        1 => Ok(blocks.pop().unwrap()),
+        _ => Err(anyhow!("unclosed loop at the end of the input")),
+    }
+}
+
+/* fonction permettant de vérifier que chaque suite de multiplications est suivie de la remise à 0 qui termine sa boucle */
+fn check_multiplications(ist_list: &[FlattenedInstruction]) -> Result<()> {
+    let mut pending = false;
+    for ist in ist_list {
+        match ist {
+            FlattenedInstruction::MulAdd { .. } => pending = true,
+            FlattenedInstruction::Clear => pending = false,
+            _ if pending => return Err(anyhow!("multiplication without clear")),
+            FlattenedInstruction::Loop(inner) => check_multiplications(inner)?,
+            _ => (),
+        }
+    }
+    if pending {
+        Err(anyhow!("multiplication without clear"))
+    } else {
+        Ok(())
+    }
+}
+
+/* fonction permettant de ramener des instructions aplaties lues telles quelles, en texte ou en json, à la forme attendue par les passes
+    les fonctions standards sont développées avec des cellules de travail après la dernière cellule atteinte
+    puis les goto sont remplacés par des déplacements, cell étant le type des cellules de la cible
+*/
+pub fn lower(
+    ist_list: Vec<FlattenedInstruction>,
+    cell: VarType,
+) -> Result<Vec<FlattenedInstruction>> {
+    check_multiplications(&ist_list)?;
+    check_cell_type(&ist_list, &Layout::default(), cell)?;
+    let ist_list = expand_builtin(ist_list, &Layout::default())?;
+    let (res, _) = transform_goto(ist_list, Outputpointer::default());
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter;
+    use crate::optimizer::{optimize_consecutive, OptLevel, PassManager};
+    use crate::producer::produce;
+    use std::fs;
+    use std::path::Path;
+
+    /* tests de référence de l'optimiseur: tests/ir/nom.in passe par la passe nom, ou par toutes celles du niveau pour O0, O1, O2 et Os
+        suivis éventuellement d'un tiret et d'une description, après être passé par lower comme les instructions lues par le compilateur
+        le résultat doit pouvoir être produit en Brainfuck et être identique à tests/ir/nom.out, ou au message d'erreur si l'entrée est refusée
+        on régénère les fichiers .out en lançant les tests avec BLESS=1
+    */
+    #[test]
+    fn optimizer_golden_files() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/ir");
+        let mut cases = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "in"))
+            .collect::<Vec<_>>();
+        cases.sort();
+        assert!(!cases.is_empty());
+        let mut failures = Vec::new();
+        for case in cases {
+            let name = case.file_stem().unwrap().to_str().unwrap();
+            let mut manager = match name.strip_prefix('O') {
+                Some(level) => {
+                    let level = level.split('-').next().unwrap();
+                    PassManager::new(OptLevel::parse(level).unwrap())
+                }
+                None => {
+                    let mut manager = PassManager::new(OptLevel::O0);
+                    manager.enable(name).unwrap();
+                    manager
+                }
+            };
+            let source = fs::read_to_string(&case).unwrap();
+            //une entrée refusée donne son message d'erreur
+            let res = match parse(&source).and_then(|ist_list| lower(ist_list, VarType::U8)) {
+                Ok(ist_list) => {
+                    let ist_list = manager.run(ist_list);
+                    //le résultat doit toujours pouvoir être produit, chaque multiplication restant suivie de sa remise à 0
+                    if let Err(err) = produce(ist_list.clone()) {
+                        failures.push(format!("{}: {}", name, err));
+                    }
+                    dump(&ist_list)
+                }
+                Err(err) => format!("error: {}\n", err),
+            };
+            let expected = case.with_extension("out");
+            if std::env::var_os("BLESS").is_some() {
+                fs::write(&expected, &res).unwrap();
+            } else if fs::read_to_string(&expected).ok().as_deref() != Some(res.as_str()) {
+                failures.push(format!("{}:\n{}", name, res));
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn lower_read_instructions() {
+        let run = |source: &str| {
+            let ist_list = lower(parse(source).unwrap(), VarType::U8).unwrap();
+            interpreter::run(&produce(ist_list).unwrap(), &[])
+                .unwrap()
+                .output
+        };
+        assert_eq!(run("goto 2\nadd 65\nprint\n"), b"A");
+        //les cellules de travail sont placées après la cellule 3 atteinte par un déplacement relatif
+        assert_eq!(
+            run(
+                "right 3\nadd 7\nleft 3\nsetconst 42\nbuiltin printnum 0\ngoto 0\nright 3\nprint\n"
+            ),
+            b"42\x07"
+        );
+        let json = r#"[{"Goto":2},{"Add":65},"Print"]"#;
+        let ist_list = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            produce(lower(ist_list, VarType::U8).unwrap()).unwrap(),
+            ">>\n+++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++\n."
+        );
+        assert!(lower(parse("muladd 1 1\nprint\nclear\n").unwrap(), VarType::U8).is_err());
+        assert!(lower(parse("loop {\nmuladd 1 1\n}\n").unwrap(), VarType::U8).is_err());
+        assert!(lower(
+            parse("muladd 1 1\nmuladd 2 1\nclear\n").unwrap(),
+            VarType::U8
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn ir_golden() {
+        let source = "goto 2\nadd 3\nloop {\n    sub 1\n    left 1\n    add 2\n    right 1\n}\n\
+                      line 4 in double at cell 1\nleft 1\nscanright 1\nbuiltin printnum 0\nadd 1\nadd 1\n";
+        let ist_list = parse(source).unwrap();
+        assert_eq!(
+            dump(&ist_list),
+            "goto 2 ; @0\nadd 3 ; @2\nloop { ; @2\n    sub 1 ; @2\n    left 1 ; @2\n    add 2 ; @1\n    \
+             right 1 ; @1\n}\nline 4 in double at cell 1\nleft 1 ; @2\nscanright 1 ; @1\n\
+             builtin printnum 0\nadd 1\nadd 1\n"
+        );
+        assert_eq!(parse(&dump(&ist_list)).unwrap(), ist_list);
+        let ist_list = ist_list
+            .into_iter()
+            .filter(|ist| !matches!(ist, FlattenedInstruction::Builtin(..)))
+            .collect();
+        assert_eq!(
+            dump(&optimize_consecutive(ist_list)),
+            "goto 2 ; @0\nadd 3 ; @2\nmuladd -1 2 ; @2\nclear ; @2\n\
+             line 4 in double at cell 1\nleft 1 ; @2\nscanright 1 ; @1\nadd 2\n"
+        );
+        assert!(parse("loop {\nadd 1\n").is_err());
+        assert!(parse("}").is_err());
+        assert!(parse("add").is_err());
+        assert!(parse("builtin mul 1 2").is_err());
+    }
+}
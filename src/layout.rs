@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::instruction::Instruction;
+use crate::variable::Variable;
+
+/* type d'une variable déclarée, les entiers de plus de 8 bits occupent plusieurs cellules consécutives (octet de poids faible en premier) */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VarType {
+    U8,
+    U16,
+    U32,
+}
+
+impl VarType {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "u8" => Ok(Self::U8),
+            "u16" => Ok(Self::U16),
+            "u32" => Ok(Self::U32),
+            _ => Err(anyhow!("unknown variable type {}", name)),
+        }
+    }
+
+    #[inline]
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+        }
+    }
+
+    /* nombre de cellules occupées par la variable */
+    #[inline]
+    pub const fn width(&self) -> usize {
+        match self {
+            Self::U8 => 1,
+            Self::U16 => 2,
+            Self::U32 => 4,
+        }
+    }
+}
+
+/* structure permettant de placer les variables et tableaux déclarés sur la bande, dans l'ordre de déclaration à partir de la cellule 0
+    les piles n'ont pas de taille connue, elles sont entrelacées à la fin de la bande et désignées par leur numéro
+*/
+#[derive(Debug, Default, Clone)]
+pub struct Layout {
+    variables: HashMap<String, (usize, VarType)>,
+    arrays: HashMap<String, (usize, usize)>,
+    stacks: HashMap<String, usize>,
+    next: usize,
+}
+
+impl Layout {
+    fn is_declared(&self, name: &str) -> bool {
+        self.variables.contains_key(name)
+            || self.arrays.contains_key(name)
+            || self.stacks.contains_key(name)
+    }
+
+    pub fn declare(&mut self, name: &str, ty: VarType) -> Result<()> {
+        if self.is_declared(name) {
+            return Err(anyhow!("try to redeclare variable {}", name));
+        }
+        self.variables.insert(name.to_owned(), (self.next, ty));
+        self.next += ty.width();
+        Ok(())
+    }
+
+    /* un tableau de n éléments occupe n + 1 emplacements de trois cellules, le premier sert de garde au curseur */
+    pub fn declare_array(&mut self, name: &str, len: usize) -> Result<()> {
+        if self.is_declared(name) {
+            return Err(anyhow!("try to redeclare variable {}", name));
+        }
+        self.arrays.insert(name.to_owned(), (self.next, len));
+        self.next += 3 * (len + 1);
+        Ok(())
+    }
+
+    pub fn declare_stack(&mut self, name: &str) -> Result<()> {
+        if self.is_declared(name) {
+            return Err(anyhow!("try to redeclare variable {}", name));
+        }
+        self.stacks.insert(name.to_owned(), self.stacks.len());
+        Ok(())
+    }
+
+    #[inline]
+    pub fn stack_count(&self) -> usize {
+        self.stacks.len()
+    }
+
+    /* nombre d'éléments du tableau commençant à cette adresse */
+    pub fn array_len(&self, cell: usize) -> Option<usize> {
+        self.arrays
+            .values()
+            .find(|(start, _)| *start == cell)
+            .map(|(_, len)| *len)
+    }
+
+    /* fonction permettant de remplacer une variable par son adresse (son numéro pour une pile), les littéraux sont laissés tels quels */
+    pub fn resolve(&self, var: &Variable) -> Result<usize> {
+        match var {
+            Variable::Constant(value) => Ok(*value),
+            Variable::Named(name) => self
+                .variables
+                .get(name)
+                .map(|(cell, _)| *cell)
+                .or_else(|| self.arrays.get(name).map(|(cell, _)| *cell))
+                .or_else(|| self.stacks.get(name).copied())
+                .context(format!("try to use an undeclared variable {}", name)),
+        }
+    }
+
+    /* nombre de cellules de la variable commençant à cette adresse, 1 pour une cellule non déclarée */
+    pub fn width_of(&self, cell: usize) -> usize {
+        self.variables
+            .values()
+            .find(|(start, _)| *start == cell)
+            .map_or(1, |(_, ty)| ty.width())
+    }
+
+    /* vrai si une variable déclarée occupe plusieurs cellules */
+    pub fn has_wide_variables(&self) -> bool {
+        self.variables.values().any(|(_, ty)| ty.width() > 1)
+    }
+
+    /* première cellule après les variables déclarées */
+    #[inline]
+    pub fn end(&self) -> usize {
+        self.next
+    }
+
+    /* noms des variables et tableaux, dans l'ordre où ils sont placés sur la bande */
+    pub fn declarations(&self) -> Vec<String> {
+        let mut res = self
+            .variables
+            .iter()
+            .map(|(name, (start, _))| (*start, name))
+            .chain(self.arrays.iter().map(|(name, (start, _))| (*start, name)))
+            .collect::<Vec<_>>();
+        res.sort();
+        res.into_iter().map(|(_, name)| name.to_owned()).collect()
+    }
+
+    /* nombre de cellules occupées par une variable ou un tableau */
+    pub fn size_of(&self, name: &str) -> Option<usize> {
+        self.variables
+            .get(name)
+            .map(|(_, ty)| ty.width())
+            .or_else(|| self.arrays.get(name).map(|(_, len)| 3 * (len + 1)))
+    }
+
+    /* fonction permettant de replacer les variables et tableaux dans un autre ordre, les piles gardent leur numéro */
+    pub fn arrange(&self, order: &[String]) -> Result<Self> {
+        let mut res = Self {
+            stacks: self.stacks.clone(),
+            ..Self::default()
+        };
+        for name in order {
+            match (self.variables.get(name), self.arrays.get(name)) {
+                (Some((_, ty)), _) => res.declare(name, *ty)?,
+                (_, Some((_, len))) => res.declare_array(name, *len)?,
+                _ => return Err(anyhow!("try to place an undeclared variable {}", name)),
+            }
+        }
+        Ok(res)
+    }
+
+    /* description de la bande, une ligne par nom: première cellule, nombre de cellules, nom et type */
+    pub fn map(&self) -> String {
+        let mut res = String::new();
+        for name in self.declarations() {
+            let line = match (self.variables.get(&name), self.arrays.get(&name)) {
+                (Some((start, ty)), _) => {
+                    format!("{}\t{}\t{}\t{}\n", start, ty.width(), name, ty.name())
+                }
+                (_, Some((start, len))) => {
+                    format!("{}\t{}\t{}\tarray[{}]\n", start, 3 * (len + 1), name, len)
+                }
+                _ => unreachable!(),
+            };
+            res.push_str(&line);
+        }
+        let mut stacks = self.stacks.iter().collect::<Vec<_>>();
+        stacks.sort_by_key(|(_, index)| **index);
+        for (name, index) in stacks {
+            res.push_str(&format!("-\t-\t{}\tstack {}\n", name, index));
+        }
+        res
+    }
+}
+
+/* fonction permettant de séparer les déclarations de variables, de tableaux et de piles du reste des instructions */
+pub fn produce_layout(ist_list: Vec<Instruction>) -> Result<(Vec<Instruction>, Layout)> {
+    let mut layout = Layout::default();
+    let mut res = Vec::new();
+    for ist in ist_list {
+        match ist {
+            Instruction::Var(name, ty) => layout.declare(&name, ty)?,
+            Instruction::Array(name, len) => layout.declare_array(&name, len)?,
+            Instruction::Stack(name) => layout.declare_stack(&name)?,
+            e => res.push(e),
+        }
+    }
+    Ok((res, layout))
+}
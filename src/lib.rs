@@ -0,0 +1,31 @@
+pub mod backend;
+pub mod builtin;
+pub mod cli;
+pub mod code_checker;
+pub mod context;
+pub mod decompiler;
+pub mod dialect;
+pub mod function;
+pub mod instruction;
+pub mod interpreter;
+pub mod ir;
+pub mod layout;
+pub mod offset;
+pub mod optimizer;
+pub mod placement;
+pub mod producer;
+pub mod variable;
+pub mod vm;
+
+extern crate pest;
+#[macro_use]
+extern crate pest_derive;
+#[macro_use]
+extern crate lazy_static;
+
+lazy_static! {
+    static ref STD_FUNCTION: Vec<&'static str> = vec![
+        "SetConst", "Goto", "Add", "Sub", "Print", "Read", "PrintNum", "ReadNum", "Mul", "DivMod",
+        "Eq", "Lt", "Not", "Load", "Store", "Push", "Pop", "IsEmpty"
+    ];
+}
@@ -1,62 +1,148 @@
-mod code_checker;
-mod context;
-mod function;
-mod instruction;
-mod optimizer;
-mod producer;
-mod variable;
-
-use std::collections::{HashMap, HashSet};
+use std::collections::HashSet;
 use std::fs;
-use std::io::Write;
-
-use anyhow::{anyhow, Context, Result};
-use pest::Parser;
-extern crate pest;
-use pest::iterators::Pair;
-#[macro_use]
-extern crate pest_derive;
-#[macro_use]
-extern crate lazy_static;
-
-use code_checker::{transform_goto, Outputpointer};
-use function::{produce_mapping, replace_function};
-use instruction::Instruction;
-use optimizer::optimize_consecutive;
-use producer::produce_string;
+use std::io::{Read, Write};
 
-lazy_static! {
-    static ref STD_FUNCTION: Vec<&'static str> = vec!["SetConst", "Goto", "Add", "Sub", "Print"];
-}
+use bf_il::backend::c::produce_c;
+use bf_il::backend::rust::{produce_rust, RustOptions};
+use bf_il::backend::wat::produce_wat;
+use bf_il::backend::x86_64::produce_x86_64;
+use bf_il::backend::Target;
+use bf_il::builtin::{check_cell_type, expand_builtin};
+use bf_il::cli::{Emit, InputFormat, Options};
+use bf_il::code_checker::{transform_goto, Outputpointer};
+use bf_il::decompiler::{decompile, parse_brainfuck};
+use bf_il::dialect::{Dialect, TokenMap};
+use bf_il::function::{produce_mapping, replace_function, strip_annotations};
+use bf_il::instruction::Instruction;
+use bf_il::interpreter;
+use bf_il::ir;
+use bf_il::layout::produce_layout;
+use bf_il::optimizer::{format_stats, PassManager};
+use bf_il::placement::arrange_layout;
+use bf_il::producer::produce;
+use bf_il::vm::Vm;
 
 fn main() {
-    /* let name = std::env::args()
-    .nth(1)
-    .expect("you need to specify a file to compile"); */
-    let name = "test.bfil";
-    let asm_filename = "test.bf";
-    let file = fs::read_to_string(name).expect("cannot read file");
-    let (ist_res, function_name) = Instruction::parse(file); //.expect("failed to parse programs")
-    let mut ist = ist_res.expect("failed to parse programs");
-    dbg!(&ist);
-    let (ist, mapping) = produce_mapping(ist, function_name).expect("cannot produce mapping");
-    let mut set = HashSet::new();
-    let ist = replace_function(&ist, &mapping, &mut set).expect("cannot replace function");
+    let options = Options::parse(std::env::args().skip(1)).expect("invalid arguments");
+    let file = fs::read_to_string(&options.input).expect("cannot read file");
+    if options.decompile {
+        let source = decompile(&file).expect("failed to decompile the brainfuck program");
+        write_output(&options.output, &source);
+        return;
+    }
+    let dialect = match &options.token_map {
+        Some(file) => Dialect::Tokens(
+            TokenMap::parse(&fs::read_to_string(file).expect("cannot read the token map"))
+                .expect("invalid token map"),
+        ),
+        None => options.dialect.clone(),
+    };
+    let ist = match options.input_format {
+        InputFormat::Bfil => {
+            let (ist_res, function_name) = Instruction::parse(file); //.expect("failed to parse programs")
+            let ist = ist_res.expect("failed to parse programs");
+            if options.emit == Emit::AstJson {
+                let json = serde_json::to_string_pretty(&ist).expect("failed to serialize the ast");
+                write_output(&options.output, &(json + "\n"));
+                return;
+            }
+            let (ist, mapping) =
+                produce_mapping(ist, function_name).expect("cannot produce mapping");
+            let (ist, layout) = produce_layout(ist).expect("cannot produce layout");
+            let layout = arrange_layout(&ist, &mapping, &layout).expect("cannot arrange layout");
+            if let Some(map) = &options.map {
+                fs::write(map, layout.map()).expect("failed to write the map file");
+            }
+            let mut set = HashSet::new();
+            let mut ist = replace_function(&ist, &mapping, &mut set, &layout)
+                .expect("cannot replace function");
+            if !options.annotate && !dialect.needs_annotations() {
+                ist = strip_annotations(ist);
+            }
+            check_cell_type(&ist, &layout, options.cell).expect("unsupported cell type");
+            let ist = expand_builtin(ist, &layout).expect("cannot expand builtin");
+
+            let (res, _) = transform_goto(ist, Outputpointer::default());
+            res.expect("failed to transform goto")
+        }
+        InputFormat::Bf => parse_brainfuck(&file).expect("failed to parse the brainfuck program"),
+        InputFormat::Ir => ir::parse(&file)
+            .and_then(|ist| ir::lower(ist, options.cell))
+            .expect("failed to parse the instructions"),
+        InputFormat::FlatJson => serde_json::from_str(&file)
+            .map_err(anyhow::Error::from)
+            .and_then(|ist| ir::lower(ist, options.cell))
+            .expect("failed to parse the flattened instructions"),
+    };
 
-    dbg!(&ist);
-    let (mut res, _) = transform_goto(ist, Outputpointer::default());
+    let mut manager =
+        PassManager::new(options.level).with_cell_bits(options.cell.width() as u32 * 8);
+    for name in &options.passes {
+        manager.enable(name).expect("cannot enable pass");
+    }
+    for name in &options.no_passes {
+        manager.disable(name).expect("cannot disable pass");
+    }
+    if options.stats {
+        manager = manager.with_stats();
+    }
+    let ist = manager.run(ist);
+    if options.stats {
+        eprint!("{}", format_stats(manager.stats()));
+    }
+    if options.run {
+        let mut input = Vec::new();
+        std::io::stdin()
+            .read_to_end(&mut input)
+            .expect("failed to read the input");
+        let machine = if options.fast {
+            Vm::new(&ist).run(&input)
+        } else {
+            produce(ist).and_then(|code| interpreter::run(&code, &input))
+        }
+        .expect("failed to run the program");
+        std::io::stdout()
+            .write_all(&machine.output)
+            .expect("failed to write the output");
+        return;
+    }
 
-    let brainfuck_code = optimize_consecutive(res.expect("failed to transform goto"))
-        .into_iter()
-        .map(|ist_inner| produce_string(ist_inner))
-        .collect::<String>();
+    let brainfuck_code = match options.emit {
+        Emit::Bf => match options.target {
+            Target::Bf => dialect.render(
+                &produce(ist).expect("cannot produce brainfuck"),
+                options.format,
+                options.width,
+            ),
+            Target::C => produce_c(&ist),
+            Target::Rust => produce_rust(
+                &ist,
+                RustOptions {
+                    cell: options.cell,
+                    tape_size: options.tape_size,
+                },
+            ),
+            Target::Wat => produce_wat(&ist, options.tape_size),
+            Target::X86_64 => produce_x86_64(&ist, options.tape_size),
+        },
+        Emit::Flat => format!("{:#?}\n", ist),
+        Emit::FlatJson => {
+            serde_json::to_string_pretty(&ist).expect("failed to serialize the instructions") + "\n"
+        }
+        Emit::Ir => ir::dump(&ist),
+        Emit::AstJson => unreachable!(), //écrit juste après le parsing
+    };
 
-    let mut res_file =
-        fs::File::create(asm_filename).expect("failed to created the file for the asm");
-    res_file
-        .write_all(brainfuck_code.as_bytes())
-        .expect("failed to write brainfuck code");
+    write_output(&options.output, &brainfuck_code);
+}
 
-    dbg!(brainfuck_code);
-    println!("Hello, world!");
+/* écrit le résultat dans le fichier de sortie, ou sur la sortie standard avec -o - */
+fn write_output(path: &str, content: &str) {
+    if path == "-" {
+        std::io::stdout()
+            .write_all(content.as_bytes())
+            .expect("failed to write the output");
+    } else {
+        fs::write(path, content).expect("failed to write the output file");
+    }
 }
@@ -0,0 +1,179 @@
+use crate::function::FlattenedInstruction;
+
+/* forme à décalages: chaque opération porte la cellule qu'elle modifie, relativement à la base du bloc, la tête de lecture ne bouge plus */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OffsetInstruction {
+    Add { offset: isize, n: usize },
+    Sub { offset: isize, n: usize },
+    SetConst { offset: isize, value: usize },
+    Clear { offset: isize },
+}
+
+impl OffsetInstruction {
+    #[inline]
+    pub const fn offset(&self) -> isize {
+        match self {
+            Self::Add { offset, .. }
+            | Self::Sub { offset, .. }
+            | Self::SetConst { offset, .. }
+            | Self::Clear { offset } => *offset,
+        }
+    }
+
+    /* instruction équivalente une fois la tête de lecture placée sur la cellule */
+    fn at_head(&self) -> FlattenedInstruction {
+        match self {
+            Self::Add { n, .. } => FlattenedInstruction::Add(*n),
+            Self::Sub { n, .. } => FlattenedInstruction::Sub(*n),
+            Self::SetConst { value, .. } => FlattenedInstruction::SetConst(*value),
+            Self::Clear { .. } => FlattenedInstruction::Clear,
+        }
+    }
+}
+
+/* déplacement de la tête de lecture de from à to, rien si elle y est déjà */
+fn travel(from: isize, to: isize) -> Option<FlattenedInstruction> {
+    match to - from {
+        0 => None,
+        d if d > 0 => Some(FlattenedInstruction::Right(d as usize)),
+        d => Some(FlattenedInstruction::Left(d.unsigned_abs())),
+    }
+}
+
+/* suite d'opérations sur des cellules à une position connue par rapport à la base, la tête de lecture devant finir end cellules après la base
+    les déplacements ne sont faits qu'à la fin du bloc, c'est à dire avant une boucle ou une entrée-sortie
+*/
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub ist_list: Vec<OffsetInstruction>,
+    pub end: isize,
+}
+
+impl Block {
+    /* instructions que l'on peut mettre sous forme à décalages */
+    pub fn accepts(ist: &FlattenedInstruction) -> bool {
+        matches!(
+            ist,
+            FlattenedInstruction::Noop
+                | FlattenedInstruction::Add(_)
+                | FlattenedInstruction::Sub(_)
+                | FlattenedInstruction::SetConst(_)
+                | FlattenedInstruction::Clear
+                | FlattenedInstruction::Left(_)
+                | FlattenedInstruction::Right(_)
+        )
+    }
+
+    /* fonction permettant de construire un bloc à partir d'instructions aplaties, None si l'une d'elle dépend de la position réelle de la tête de lecture */
+    pub fn from_flat(ist_list: &[FlattenedInstruction]) -> Option<Self> {
+        let mut block = Self::default();
+        for ist in ist_list {
+            let offset = block.end;
+            match ist {
+                FlattenedInstruction::Noop => (),
+                FlattenedInstruction::Add(n) => block
+                    .ist_list
+                    .push(OffsetInstruction::Add { offset, n: *n }),
+                FlattenedInstruction::Sub(n) => block
+                    .ist_list
+                    .push(OffsetInstruction::Sub { offset, n: *n }),
+                FlattenedInstruction::SetConst(value) => {
+                    block.ist_list.push(OffsetInstruction::SetConst {
+                        offset,
+                        value: *value,
+                    })
+                }
+                FlattenedInstruction::Clear => {
+                    block.ist_list.push(OffsetInstruction::Clear { offset })
+                }
+                FlattenedInstruction::Left(v) => block.end -= *v as isize,
+                FlattenedInstruction::Right(v) => block.end += *v as isize,
+                _ => return None,
+            }
+        }
+        Some(block)
+    }
+
+    /* fonction permettant de revenir aux instructions aplaties
+        les opérations sur des cellules différentes sont indépendantes, on visite donc les cellules de proche en proche depuis l'extrémité
+        qui donne le trajet le plus court jusqu'à la fin du bloc, les opérations sur une même cellule gardant leur ordre
+    */
+    pub fn lower(&self) -> Vec<FlattenedInstruction> {
+        let mut cells = self
+            .ist_list
+            .iter()
+            .map(OffsetInstruction::offset)
+            .collect::<Vec<_>>();
+        cells.sort_unstable();
+        cells.dedup();
+
+        if let (Some(&low), Some(&high)) = (cells.first(), cells.last()) {
+            let low_first = low.abs() + (high - self.end).abs();
+            let high_first = high.abs() + (low - self.end).abs();
+            if high_first < low_first {
+                cells.reverse();
+            }
+        }
+
+        let mut res = Vec::new();
+        let mut position = 0;
+        for cell in cells {
+            res.extend(travel(position, cell));
+            res.extend(
+                self.ist_list
+                    .iter()
+                    .filter(|ist| ist.offset() == cell)
+                    .map(OffsetInstruction::at_head),
+            );
+            position = cell;
+        }
+        res.extend(travel(position, self.end));
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use FlattenedInstruction::*;
+
+    #[test]
+    fn block_round_trip() {
+        let flat = vec![
+            Right(3),
+            Add(1),
+            Left(3),
+            SetConst(2),
+            Right(3),
+            Sub(4),
+            Left(1),
+        ];
+        let block = Block::from_flat(&flat).unwrap();
+        assert_eq!(
+            block,
+            Block {
+                ist_list: vec![
+                    OffsetInstruction::Add { offset: 3, n: 1 },
+                    OffsetInstruction::SetConst {
+                        offset: 0,
+                        value: 2
+                    },
+                    OffsetInstruction::Sub { offset: 3, n: 4 },
+                ],
+                end: 2,
+            }
+        );
+        assert_eq!(
+            block.lower(),
+            vec![SetConst(2), Right(3), Add(1), Sub(4), Left(1)]
+        );
+        //on commence par l'extrémité opposée à celle où le bloc finit
+        assert_eq!(
+            Block::from_flat(&[Left(3), Add(1), Right(5), Add(2), Left(6)])
+                .unwrap()
+                .lower(),
+            vec![Right(2), Add(2), Left(5), Add(1), Left(1)]
+        );
+        assert_eq!(Block::from_flat(&[Add(1), Print]), None);
+    }
+}
@@ -1,52 +1,1462 @@
 use crate::function::FlattenedInstruction;
-use std::collections::VecDeque;
+use crate::offset::Block;
+use crate::producer::produce;
+use anyhow::{anyhow, Result};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 
-pub enum InstructionKind {
-    Linear,
-    Replaceable,
-    Any,
+/* une passe d'optimisation sur le code aplati, elle doit conserver la sémantique du programme
+    une passe transforme une suite d'instructions d'un même niveau, le contenu des boucles étant transformé avant la boucle elle-même
+*/
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    fn run_level(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction>;
+
+    fn run(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        let ist_list = ist_list
+            .into_iter()
+            .map(|ist| match ist {
+                FlattenedInstruction::Loop(inner) => FlattenedInstruction::Loop(self.run(inner)),
+                e => e,
+            })
+            .collect();
+        self.run_level(ist_list)
+    }
 }
-/* fonction permettant d'optimiser en modifiant dans certain cas deux instruction succéssive   */
-pub fn optimize_consecutive(ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
-    let mut res = VecDeque::new();
 
+/* résultat d'une règle appliquée à deux instructions consécutives */
+enum Rewrite {
+    Keep,                          //la règle ne s'applique pas
+    Remove,                        //les deux instructions s'annulent
+    Replace(FlattenedInstruction), //les deux instructions sont fusionnées
+}
+
+/* fonction permettant d'appliquer une règle à chaque paire d'instructions consécutives (la plus ancienne en premier)
+    le résultat d'une fusion est de nouveau comparé à l'instruction qui le précède, une annulation pouvant en rendre possible une autre
+*/
+fn peephole(
+    ist_list: Vec<FlattenedInstruction>,
+    rule: fn(&FlattenedInstruction, &FlattenedInstruction) -> Rewrite,
+) -> Vec<FlattenedInstruction> {
+    let mut res: Vec<FlattenedInstruction> = Vec::new();
     for ist in ist_list {
-        if res.is_empty() {
-            res.push_back(ist);
+        let mut current = Some(ist);
+        while let Some(ist) = current.take() {
+            match res
+                .last()
+                .map_or(Rewrite::Keep, |previous| rule(previous, &ist))
+            {
+                Rewrite::Keep => res.push(ist),
+                Rewrite::Remove => {
+                    res.pop();
+                }
+                Rewrite::Replace(merged) => {
+                    res.pop();
+                    current = Some(merged);
+                }
+            }
+        }
+    }
+    res
+}
+
+/* fonction permettant de remplacer deux opérations opposées par leur opération nette, ou par rien si elles s'annulent */
+fn cancel(
+    added: usize,
+    removed: usize,
+    positive: fn(usize) -> FlattenedInstruction,
+    negative: fn(usize) -> FlattenedInstruction,
+) -> Rewrite {
+    match added.cmp(&removed) {
+        Ordering::Greater => Rewrite::Replace(positive(added - removed)),
+        Ordering::Less => Rewrite::Replace(negative(removed - added)),
+        Ordering::Equal => Rewrite::Remove,
+    }
+}
+
+/* passe supprimant les instructions sans effet */
+pub struct RemoveNoop;
+
+impl Pass for RemoveNoop {
+    fn name(&self) -> &'static str {
+        "remove-noop"
+    }
+
+    fn run_level(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        ist_list
+            .into_iter()
+            .filter(|ist| {
+                !matches!(
+                    ist,
+                    FlattenedInstruction::Noop
+                        | FlattenedInstruction::Add(0)
+                        | FlattenedInstruction::Sub(0)
+                        | FlattenedInstruction::Left(0)
+                        | FlattenedInstruction::Right(0)
+                )
+            })
+            .collect()
+    }
+}
+
+/* passe fusionnant les opérations consécutives sur la cellule courante
+    une affectation n'est fusionnée qu'avec ce qui la suit immédiatement, sans autre instruction entre les deux
+*/
+pub struct CombineArithmetic;
+
+impl Pass for CombineArithmetic {
+    fn name(&self) -> &'static str {
+        "combine-arithmetic"
+    }
+
+    fn run_level(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        peephole(ist_list, |previous, next| match (previous, next) {
+            (FlattenedInstruction::Add(v1), FlattenedInstruction::Add(v2)) => {
+                Rewrite::Replace(FlattenedInstruction::Add(v1 + v2))
+            }
+            (FlattenedInstruction::Sub(v1), FlattenedInstruction::Sub(v2)) => {
+                Rewrite::Replace(FlattenedInstruction::Sub(v1 + v2))
+            }
+            (FlattenedInstruction::Add(v1), FlattenedInstruction::Sub(v2)) => cancel(
+                *v1,
+                *v2,
+                FlattenedInstruction::Add,
+                FlattenedInstruction::Sub,
+            ),
+            (FlattenedInstruction::Sub(v1), FlattenedInstruction::Add(v2)) => cancel(
+                *v2,
+                *v1,
+                FlattenedInstruction::Add,
+                FlattenedInstruction::Sub,
+            ),
+            (
+                FlattenedInstruction::SetConst(_)
+                | FlattenedInstruction::Add(_)
+                | FlattenedInstruction::Sub(_),
+                FlattenedInstruction::SetConst(val),
+            ) => Rewrite::Replace(FlattenedInstruction::SetConst(*val)),
+            (FlattenedInstruction::SetConst(val), FlattenedInstruction::Add(v)) => {
+                Rewrite::Replace(FlattenedInstruction::SetConst(val + v))
+            }
+            (FlattenedInstruction::SetConst(val), FlattenedInstruction::Sub(v)) if v <= val => {
+                Rewrite::Replace(FlattenedInstruction::SetConst(val - v))
+            }
+            _ => Rewrite::Keep,
+        })
+    }
+}
+
+/* passe fusionnant les déplacements consécutifs de la tête de lecture */
+pub struct CombineMovement;
+
+impl Pass for CombineMovement {
+    fn name(&self) -> &'static str {
+        "combine-movement"
+    }
+
+    fn run_level(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        peephole(ist_list, |previous, next| match (previous, next) {
+            (FlattenedInstruction::Left(v1), FlattenedInstruction::Left(v2)) => {
+                Rewrite::Replace(FlattenedInstruction::Left(v1 + v2))
+            }
+            (FlattenedInstruction::Right(v1), FlattenedInstruction::Right(v2)) => {
+                Rewrite::Replace(FlattenedInstruction::Right(v1 + v2))
+            }
+            (FlattenedInstruction::Right(v1), FlattenedInstruction::Left(v2)) => cancel(
+                *v1,
+                *v2,
+                FlattenedInstruction::Right,
+                FlattenedInstruction::Left,
+            ),
+            (FlattenedInstruction::Left(v1), FlattenedInstruction::Right(v2)) => cancel(
+                *v2,
+                *v1,
+                FlattenedInstruction::Right,
+                FlattenedInstruction::Left,
+            ),
+            (FlattenedInstruction::Goto(_), FlattenedInstruction::Goto(val)) => {
+                Rewrite::Replace(FlattenedInstruction::Goto(*val))
+            }
+            _ => Rewrite::Keep,
+        })
+    }
+}
+
+/* ce que l'on sait de la machine à un point du programme, la bande commençant remplie de 0
+    les valeurs sont prises modulo la taille des cellules
+*/
+#[derive(Debug, Clone)]
+struct Knowledge {
+    pointer: Option<usize>,
+    values: HashMap<usize, Option<u64>>, //None: la valeur de la cellule est inconnue
+    zeroed: bool,                        //les cellules absentes de values valent encore 0
+    modulus: u64,
+}
+
+impl Knowledge {
+    fn new(cell_bits: u32) -> Self {
+        Self {
+            pointer: Some(0),
+            values: HashMap::new(),
+            zeroed: true,
+            modulus: 1 << cell_bits,
+        }
+    }
+
+    fn value(&self, cell: usize) -> Option<u64> {
+        match self.values.get(&cell) {
+            Some(value) => *value,
+            None if self.zeroed => Some(0),
+            None => None,
+        }
+    }
+
+    fn current(&self) -> Option<u64> {
+        self.pointer.and_then(|cell| self.value(cell))
+    }
+
+    /* une écriture à une position inconnue peut toucher n'importe quelle cellule */
+    fn set_current(&mut self, value: Option<u64>) {
+        match self.pointer {
+            Some(cell) => {
+                self.values.insert(cell, value);
+            }
+            None => self.forget_all(),
+        }
+    }
+
+    fn forget(&mut self, cells: &HashSet<usize>) {
+        self.values.extend(cells.iter().map(|cell| (*cell, None)));
+    }
+
+    fn forget_all(&mut self) {
+        self.values.clear();
+        self.zeroed = false;
+    }
+
+    /* décalage d'une cellule voisine de la cellule courante valant 0, qui peut servir de compteur */
+    fn free_neighbour(&self) -> Option<isize> {
+        let cell = self.pointer?;
+        if self.value(cell + 1) == Some(0) {
+            Some(1)
+        } else if cell > 0 && self.value(cell - 1) == Some(0) {
+            Some(-1)
         } else {
-            match (ist, res.pop_back().unwrap()) {
-                (FlattenedInstruction::Add(v1), FlattenedInstruction::Add(v2)) => {
-                    res.push_back(FlattenedInstruction::Add(v1 + v2));
+            None
+        }
+    }
+
+    /* fonction permettant de mettre à jour ce que l'on sait après une instruction, le contenu des boucles est parcouru avec rewrite */
+    fn step(
+        &mut self,
+        ist: FlattenedInstruction,
+        rewrite: &dyn Fn(&Knowledge, FlattenedInstruction) -> Vec<FlattenedInstruction>,
+    ) -> FlattenedInstruction {
+        match ist {
+            FlattenedInstruction::SetConst(val) => {
+                self.set_current(Some(val as u64 % self.modulus));
+                ist
+            }
+            FlattenedInstruction::Add(v) => {
+                let value = self.current().map(|c| (c + v as u64) % self.modulus);
+                self.set_current(value);
+                ist
+            }
+            FlattenedInstruction::Sub(v) => {
+                let value = self
+                    .current()
+                    .map(|c| (c + self.modulus - v as u64 % self.modulus) % self.modulus);
+                self.set_current(value);
+                ist
+            }
+            FlattenedInstruction::Read => {
+                self.set_current(None);
+                ist
+            }
+            FlattenedInstruction::Right(v) => {
+                self.pointer = self.pointer.map(|cell| cell + v);
+                ist
+            }
+            FlattenedInstruction::Left(v) => {
+                self.pointer = self.pointer.and_then(|cell| cell.checked_sub(v));
+                ist
+            }
+            FlattenedInstruction::Sync(cell) => {
+                self.pointer = Some(cell);
+                ist
+            }
+            FlattenedInstruction::Loop(inner) => {
+                //au début de chaque tour, seules les cellules que la boucle ne modifie pas gardent leur valeur
+                let balanced = self
+                    .pointer
+                    .and_then(|cell| written_cells(&inner, cell).filter(|(_, end)| *end == cell));
+                match balanced {
+                    Some((cells, cell)) => {
+                        self.forget(&cells);
+                        self.values.insert(cell, None);
+                        let inner = track(inner, &mut self.clone(), rewrite);
+                        self.values.insert(cell, Some(0));
+                        FlattenedInstruction::Loop(inner)
+                    }
+                    None => {
+                        self.forget_all();
+                        self.pointer = None;
+                        FlattenedInstruction::Loop(track(inner, &mut self.clone(), rewrite))
+                    }
                 }
-                (FlattenedInstruction::Sub(v1), FlattenedInstruction::Sub(v2)) => {
-                    res.push_back(FlattenedInstruction::Sub(v1 + v2));
+            }
+            FlattenedInstruction::Clear => {
+                self.set_current(Some(0));
+                ist
+            }
+            FlattenedInstruction::MulAdd { offset, factor } => {
+                match self
+                    .pointer
+                    .and_then(|cell| cell.checked_add_signed(offset))
+                {
+                    Some(target) => {
+                        let factor = factor.rem_euclid(self.modulus as isize) as u64;
+                        let value = self
+                            .current()
+                            .zip(self.value(target))
+                            .map(|(c, t)| (t + c * factor) % self.modulus);
+                        self.values.insert(target, value);
+                    }
+                    None => self.forget_all(),
                 }
-                (FlattenedInstruction::Left(v1), FlattenedInstruction::Left(v2)) => {
-                    res.push_back(FlattenedInstruction::Left(v1 + v2));
+                ist
+            }
+            FlattenedInstruction::ScanLeft(_) | FlattenedInstruction::ScanRight(_) => {
+                self.pointer = None;
+                ist
+            }
+            FlattenedInstruction::Goto(_) | FlattenedInstruction::Builtin(_, _) => {
+                self.forget_all();
+                self.pointer = None;
+                ist
+            }
+            FlattenedInstruction::Noop
+            | FlattenedInstruction::Print
+            | FlattenedInstruction::Annotation(_) => ist,
+        }
+    }
+}
+
+/* fonction donnant les cellules que peut modifier une suite d'instructions commençant sur la cellule start, ainsi que la cellule d'arrivée
+    on renvoie None si le déplacement de la tête de lecture dépend des valeurs lues
+*/
+fn written_cells(
+    ist_list: &[FlattenedInstruction],
+    start: usize,
+) -> Option<(HashSet<usize>, usize)> {
+    let mut cells = HashSet::new();
+    let mut pointer = start;
+    for ist in ist_list {
+        match ist {
+            FlattenedInstruction::Add(_)
+            | FlattenedInstruction::Sub(_)
+            | FlattenedInstruction::SetConst(_)
+            | FlattenedInstruction::Read => {
+                cells.insert(pointer);
+            }
+            FlattenedInstruction::Right(v) => pointer += v,
+            FlattenedInstruction::Left(v) => pointer = pointer.checked_sub(*v)?,
+            FlattenedInstruction::Sync(cell) => pointer = *cell,
+            FlattenedInstruction::Loop(inner) => {
+                let (inner_cells, end) = written_cells(inner, pointer)?;
+                if end != pointer {
+                    return None;
                 }
-                (FlattenedInstruction::Right(v1), FlattenedInstruction::Right(v2)) => {
-                    res.push_back(FlattenedInstruction::Right(v1 + v2));
+                cells.extend(inner_cells);
+            }
+            FlattenedInstruction::Clear => {
+                cells.insert(pointer);
+            }
+            FlattenedInstruction::MulAdd { offset, .. } => {
+                cells.insert(pointer.checked_add_signed(*offset)?);
+            }
+            FlattenedInstruction::Noop
+            | FlattenedInstruction::Print
+            | FlattenedInstruction::Annotation(_) => (),
+            FlattenedInstruction::Goto(_)
+            | FlattenedInstruction::Builtin(_, _)
+            | FlattenedInstruction::ScanLeft(_)
+            | FlattenedInstruction::ScanRight(_) => return None,
+        }
+    }
+    Some((cells, pointer))
+}
+
+/* fonction parcourant le programme en suivant les valeurs connues des cellules
+    chaque instruction est d'abord réécrite par rewrite selon ce que l'on sait juste avant elle
+*/
+fn track(
+    ist_list: Vec<FlattenedInstruction>,
+    knowledge: &mut Knowledge,
+    rewrite: &dyn Fn(&Knowledge, FlattenedInstruction) -> Vec<FlattenedInstruction>,
+) -> Vec<FlattenedInstruction> {
+    let mut res = Vec::new();
+    for ist in ist_list {
+        for ist in rewrite(knowledge, ist) {
+            res.push(knowledge.step(ist, rewrite));
+        }
+    }
+    res
+}
+
+/* nombre de caractères Brainfuck produits par une suite d'instructions déjà transformée, sans affectation */
+fn code_size(ist_list: &[FlattenedInstruction]) -> usize {
+    ist_list
+        .iter()
+        .map(|ist| match ist {
+            FlattenedInstruction::Add(v)
+            | FlattenedInstruction::Sub(v)
+            | FlattenedInstruction::Left(v)
+            | FlattenedInstruction::Right(v) => *v,
+            FlattenedInstruction::Print | FlattenedInstruction::Read => 1,
+            FlattenedInstruction::Clear => 3,
+            FlattenedInstruction::Loop(inner) => 2 + code_size(inner),
+            _ => 0,
+        })
+        .sum()
+}
+
+/* ajout ou retrait le plus court pour passer de la valeur from à la valeur to, les cellules bouclant modulo modulus */
+fn relative_change(from: u64, to: u64, modulus: u64) -> Vec<FlattenedInstruction> {
+    let up = (to + modulus - from) % modulus;
+    let down = (from + modulus - to) % modulus;
+    if up == 0 {
+        vec![]
+    } else if up <= down {
+        vec![FlattenedInstruction::Add(up as usize)]
+    } else {
+        vec![FlattenedInstruction::Sub(down as usize)]
+    }
+}
+
+/* déplacement de offset cellules, vers la droite si offset est positif */
+fn shift(offset: isize) -> FlattenedInstruction {
+    if offset > 0 {
+        FlattenedInstruction::Right(offset as usize)
+    } else {
+        FlattenedInstruction::Left(offset.unsigned_abs())
+    }
+}
+
+/* facteur maximal essayé par défaut pour les boucles de multiplication */
+const MAX_FACTOR: u64 = 24;
+
+/* boucle de multiplication: valeur du compteur, facteur, et si l'on ajoute ou retire le produit */
+type Multiplication = (u64, u64, bool);
+
+/* fonction permettant de trouver la suite d'instructions la plus courte donnant la valeur to à la cellule courante
+    from est la valeur actuelle de la cellule si on la connait, sinon on commence par la remettre à 0
+    scratch est le décalage d'une cellule voisine valant 0, elle permet une boucle de multiplication et revient à 0
+*/
+fn synthesize(
+    from: Option<u64>,
+    to: u64,
+    modulus: u64,
+    scratch: Option<isize>,
+    max_factor: u64,
+) -> Vec<FlattenedInstruction> {
+    let distance = |from: u64| code_size(&relative_change(from, to, modulus));
+    //chaque candidat est donné par sa taille, le fait de remettre la cellule à 0 et l'éventuelle boucle
+    let mut starts = vec![(true, 0)];
+    if let Some(from) = from {
+        starts.insert(0, (false, from));
+    }
+
+    let mut best: Option<(usize, bool, Option<Multiplication>)> = None;
+    for (clear, base) in starts {
+        let prefix = if clear { 3 } else { 0 };
+        let mut candidates = vec![(prefix + distance(base), None)];
+        if scratch.is_some() {
+            for count in 2..=max_factor {
+                for factor in 2..=max_factor {
+                    let product = count * factor % modulus;
+                    let size = prefix + (count + factor) as usize + 7;
+                    candidates.push((
+                        size + distance((base + product) % modulus),
+                        Some((count, factor, true)),
+                    ));
+                    candidates.push((
+                        size + distance((base + modulus - product) % modulus),
+                        Some((count, factor, false)),
+                    ));
                 }
-                (FlattenedInstruction::Noop, FlattenedInstruction::Noop) => {
-                    res.push_back(FlattenedInstruction::Noop);
+            }
+        }
+        for (size, multiplication) in candidates {
+            if best.is_none_or(|(best, _, _)| size < best) {
+                best = Some((size, clear, multiplication));
+            }
+        }
+    }
+
+    let (_, clear, multiplication) = best.unwrap();
+    let mut res = Vec::new();
+    let mut reached = from.unwrap_or(0);
+    if clear {
+        res.push(FlattenedInstruction::Loop(vec![FlattenedInstruction::Sub(
+            1,
+        )]));
+        reached = 0;
+    }
+    if let (Some((count, factor, up)), Some(offset)) = (multiplication, scratch) {
+        let product = count * factor % modulus;
+        let step = if up {
+            reached = (reached + product) % modulus;
+            FlattenedInstruction::Add(factor as usize)
+        } else {
+            reached = (reached + modulus - product) % modulus;
+            FlattenedInstruction::Sub(factor as usize)
+        };
+        res.extend([
+            shift(offset),
+            FlattenedInstruction::Add(count as usize),
+            FlattenedInstruction::Loop(vec![
+                FlattenedInstruction::Sub(1),
+                shift(-offset),
+                step,
+                shift(offset),
+            ]),
+            shift(-offset),
+        ]);
+    }
+    res.extend(relative_change(reached, to, modulus));
+    res
+}
+
+/* passe suivant les valeurs connues des cellules depuis le début du programme
+    elle supprime les affectations et les boucles inutiles et remplace une affectation par un ajout quand c'est plus court
+    elle s'applique au programme entier car elle suppose la tête de lecture sur la cellule 0 d'une bande vide
+*/
+pub struct TrackValues {
+    pub cell_bits: u32,
+}
+
+impl Default for TrackValues {
+    fn default() -> Self {
+        Self { cell_bits: 8 }
+    }
+}
+
+impl Pass for TrackValues {
+    fn name(&self) -> &'static str {
+        "track-values"
+    }
+
+    fn run_level(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        track(
+            ist_list,
+            &mut Knowledge::new(self.cell_bits),
+            &|knowledge, ist| match ist {
+                FlattenedInstruction::SetConst(val) => {
+                    let to = val as u64 % knowledge.modulus;
+                    match knowledge.current() {
+                        Some(from) => {
+                            //on laisse l'affectation si sa synthèse est plus courte que l'ajout
+                            let relative = relative_change(from, to, knowledge.modulus);
+                            let synthesized = synthesize(
+                                Some(from),
+                                to,
+                                knowledge.modulus,
+                                knowledge.free_neighbour(),
+                                MAX_FACTOR,
+                            );
+                            if code_size(&relative) <= code_size(&synthesized) {
+                                relative
+                            } else {
+                                vec![FlattenedInstruction::SetConst(to as usize)]
+                            }
+                        }
+                        None => vec![FlattenedInstruction::SetConst(to as usize)],
+                    }
                 }
-                (FlattenedInstruction::Goto(val), FlattenedInstruction::Goto(_)) => {
-                    res.push_back(FlattenedInstruction::Goto(val));
+                FlattenedInstruction::Loop(_) if knowledge.current() == Some(0) => vec![], //la boucle n'est jamais exécutée
+                e => vec![e],
+            },
+        )
+    }
+
+    fn run(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        self.run_level(ist_list)
+    }
+}
+
+/* passe remplaçant chaque affectation par la suite d'instructions la plus courte, en profitant du débordement des cellules
+    et d'une cellule voisine valant 0 pour les grandes valeurs, c'est la dernière passe car elle fait disparaître les affectations
+*/
+pub struct ConstantSynthesis {
+    pub cell_bits: u32,
+    pub max_factor: u64,
+}
+
+impl Default for ConstantSynthesis {
+    fn default() -> Self {
+        Self {
+            cell_bits: 8,
+            max_factor: MAX_FACTOR,
+        }
+    }
+}
+
+impl Pass for ConstantSynthesis {
+    fn name(&self) -> &'static str {
+        "constant-synthesis"
+    }
+
+    fn run_level(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        track(
+            ist_list,
+            &mut Knowledge::new(self.cell_bits),
+            &|knowledge, ist| match ist {
+                FlattenedInstruction::SetConst(val) => synthesize(
+                    knowledge.current(),
+                    val as u64 % knowledge.modulus,
+                    knowledge.modulus,
+                    knowledge.free_neighbour(),
+                    self.max_factor,
+                ),
+                e => vec![e],
+            },
+        )
+    }
+
+    fn run(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        self.run_level(ist_list)
+    }
+}
+
+/* passe supprimant les écritures écrasées avant d'avoir été lues
+    on raisonne sur la position relative de la tête de lecture, une boucle ou un saut rendant toute cellule de nouveau utile
+*/
+pub struct DeadStore;
+
+impl Pass for DeadStore {
+    fn name(&self) -> &'static str {
+        "dead-store"
+    }
+
+    fn run_level(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        let mut offset = 0isize;
+        let offsets = ist_list
+            .iter()
+            .map(|ist| {
+                let current = offset;
+                match ist {
+                    FlattenedInstruction::Right(v) => offset += *v as isize,
+                    FlattenedInstruction::Left(v) => offset -= *v as isize,
+                    _ => (),
                 }
-                (FlattenedInstruction::SetConst(val), FlattenedInstruction::SetConst(_)) => {
-                    res.push_back(FlattenedInstruction::SetConst(val));
+                current
+            })
+            .collect::<Vec<_>>();
+
+        let mut overwritten = HashSet::new();
+        let mut keep = vec![true; ist_list.len()];
+        for (i, ist) in ist_list.iter().enumerate().rev() {
+            let offset = offsets[i];
+            match ist {
+                FlattenedInstruction::SetConst(_) => keep[i] = overwritten.insert(offset),
+                FlattenedInstruction::Add(_) | FlattenedInstruction::Sub(_) => {
+                    keep[i] = !overwritten.contains(&offset)
                 }
-                (FlattenedInstruction::Loop(inner), e) => {
-                    res.push_back(e);
-                    res.push_back(FlattenedInstruction::Loop(optimize_consecutive(inner)))
+                FlattenedInstruction::Read => {
+                    overwritten.insert(offset);
+                }
+                FlattenedInstruction::Print => {
+                    overwritten.remove(&offset);
+                }
+                FlattenedInstruction::Noop
+                | FlattenedInstruction::Annotation(_)
+                | FlattenedInstruction::Left(_)
+                | FlattenedInstruction::Right(_) => (),
+                FlattenedInstruction::Loop(_)
+                | FlattenedInstruction::Clear
+                | FlattenedInstruction::MulAdd { .. }
+                | FlattenedInstruction::ScanLeft(_)
+                | FlattenedInstruction::ScanRight(_)
+                | FlattenedInstruction::Sync(_)
+                | FlattenedInstruction::Goto(_)
+                | FlattenedInstruction::Builtin(_, _) => overwritten.clear(),
+            }
+        }
+
+        ist_list
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(ist, keep)| keep.then_some(ist))
+            .collect()
+    }
+}
+
+/* fonction permettant de remplacer une boucle par l'idiome qu'elle réalise, ou de la laisser telle quelle
+    une boucle sans déplacement net qui retire ou ajoute 1 à son compteur ajoute à chaque cellule modifiée un multiple du compteur
+*/
+fn recognize(inner: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+    match inner.as_slice() {
+        [FlattenedInstruction::Sub(1)] | [FlattenedInstruction::Add(1)] => {
+            return vec![FlattenedInstruction::Clear]
+        }
+        [FlattenedInstruction::Left(stride)] => {
+            return vec![FlattenedInstruction::ScanLeft(*stride)]
+        }
+        [FlattenedInstruction::Right(stride)] => {
+            return vec![FlattenedInstruction::ScanRight(*stride)]
+        }
+        _ => (),
+    }
+
+    let mut changes: Vec<(isize, isize)> = Vec::new();
+    let mut position = 0isize;
+    for ist in &inner {
+        let delta = match ist {
+            FlattenedInstruction::Right(v) => {
+                position += *v as isize;
+                continue;
+            }
+            FlattenedInstruction::Left(v) => {
+                position -= *v as isize;
+                continue;
+            }
+            FlattenedInstruction::Add(v) => *v as isize,
+            FlattenedInstruction::Sub(v) => -(*v as isize),
+            _ => return vec![FlattenedInstruction::Loop(inner)],
+        };
+        match changes.iter_mut().find(|(offset, _)| *offset == position) {
+            Some((_, total)) => *total += delta,
+            None => changes.push((position, delta)),
+        }
+    }
+
+    let counter = changes
+        .iter()
+        .find(|(offset, _)| *offset == 0)
+        .map_or(0, |(_, total)| *total);
+    if position != 0 || counter.abs() != 1 {
+        return vec![FlattenedInstruction::Loop(inner)];
+    }
+    //un compteur que l'on incrémente fait autant de tours que son opposé
+    changes
+        .into_iter()
+        .filter(|(offset, factor)| *offset != 0 && *factor != 0)
+        .map(|(offset, factor)| FlattenedInstruction::MulAdd {
+            offset,
+            factor: -counter * factor,
+        })
+        .chain([FlattenedInstruction::Clear])
+        .collect()
+}
+
+/* passe reconnaissant les boucles courantes (remise à 0, multiplication, recherche d'une cellule nulle)
+    pour que les backends puissent les traduire directement, le Brainfuck produit reste le même
+*/
+pub struct RecognizeIdioms;
+
+impl Pass for RecognizeIdioms {
+    fn name(&self) -> &'static str {
+        "recognize-idioms"
+    }
+
+    fn run_level(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        ist_list
+            .into_iter()
+            .flat_map(|ist| match ist {
+                FlattenedInstruction::Loop(inner) => recognize(inner),
+                e => vec![e],
+            })
+            .collect()
+    }
+}
+
+/* passe réordonnant les opérations entre deux boucles ou entrées-sorties pour réduire les déplacements de la tête de lecture */
+pub struct DeferMovement;
+
+impl Pass for DeferMovement {
+    fn name(&self) -> &'static str {
+        "defer-movement"
+    }
+
+    fn run_level(&self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        let mut res = Vec::new();
+        let mut pending = Vec::new();
+        for ist in ist_list {
+            //la remise à 0 qui suit des multiplications termine leur boucle, elle ne doit pas en être séparée
+            let ends_multiplication = matches!(ist, FlattenedInstruction::Clear)
+                && pending.is_empty()
+                && matches!(res.last(), Some(FlattenedInstruction::MulAdd { .. }));
+            if Block::accepts(&ist) && !ends_multiplication {
+                pending.push(ist);
+            } else {
+                res.extend(Block::from_flat(&pending).unwrap().lower());
+                pending.clear();
+                res.push(ist);
+            }
+        }
+        res.extend(Block::from_flat(&pending).unwrap().lower());
+        res
+    }
+}
+
+/* fonction permettant d'appliquer les passes dans l'ordre, on recommence jusqu'à ce que plus rien ne change */
+pub fn run_passes(
+    ist_list: Vec<FlattenedInstruction>,
+    passes: &[&dyn Pass],
+) -> Vec<FlattenedInstruction> {
+    let mut ist_list = ist_list;
+    loop {
+        let res = passes
+            .iter()
+            .fold(ist_list.clone(), |ist_list, pass| pass.run(ist_list));
+        if res == ist_list {
+            return res;
+        }
+        ist_list = res;
+    }
+}
+
+/* niveau d'optimisation: -O0 ne change rien, -O1 fusionne les instructions voisines, -O2 applique toutes les passes
+    et -Os cherche plus longtemps les constantes les plus courtes
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    Os,
+}
+
+impl OptLevel {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "0" => Ok(Self::O0),
+            "1" => Ok(Self::O1),
+            "2" => Ok(Self::O2),
+            "s" => Ok(Self::Os),
+            _ => Err(anyhow!("unknown optimization level {}", name)),
+        }
+    }
+
+    /* passes activées à ce niveau */
+    fn passes(&self) -> &'static [&'static str] {
+        match self {
+            Self::O0 => &[],
+            Self::O1 => &[
+                "remove-noop",
+                "combine-arithmetic",
+                "combine-movement",
+                "recognize-idioms",
+            ],
+            Self::O2 | Self::Os => &[
+                "remove-noop",
+                "combine-arithmetic",
+                "combine-movement",
+                "defer-movement",
+                "track-values",
+                "dead-store",
+                "constant-synthesis",
+                "recognize-idioms",
+            ],
+        }
+    }
+}
+
+/* étapes de l'optimisation, chacune étant répétée jusqu'à ce que plus rien ne change
+    les affectations ne sont synthétisées qu'une fois toutes les autres simplifications faites, puis on reconnait les idiomes
+*/
+const STAGES: [&[&str]; 3] = [
+    &[
+        "remove-noop",
+        "combine-arithmetic",
+        "combine-movement",
+        "defer-movement",
+        "track-values",
+        "dead-store",
+    ],
+    &[
+        "constant-synthesis",
+        "remove-noop",
+        "combine-arithmetic",
+        "combine-movement",
+    ],
+    &["recognize-idioms"],
+];
+
+fn make_pass(name: &str, level: OptLevel, cell_bits: u32) -> Box<dyn Pass> {
+    match name {
+        "remove-noop" => Box::new(RemoveNoop),
+        "combine-arithmetic" => Box::new(CombineArithmetic),
+        "combine-movement" => Box::new(CombineMovement),
+        "defer-movement" => Box::new(DeferMovement),
+        "track-values" => Box::new(TrackValues { cell_bits }),
+        "dead-store" => Box::new(DeadStore),
+        "constant-synthesis" => Box::new(ConstantSynthesis {
+            max_factor: if level == OptLevel::Os {
+                64
+            } else {
+                MAX_FACTOR
+            },
+            cell_bits,
+        }),
+        "recognize-idioms" => Box::new(RecognizeIdioms),
+        _ => unreachable!(),
+    }
+}
+
+/* nombre d'instructions, en comptant le contenu des boucles */
+fn count(ist_list: &[FlattenedInstruction]) -> usize {
+    ist_list
+        .iter()
+        .map(|ist| match ist {
+            FlattenedInstruction::Loop(inner) => 1 + count(inner),
+            _ => 1,
+        })
+        .sum()
+}
+
+/* statistiques d'une passe sur l'ensemble de ses exécutions */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassStats {
+    pub name: &'static str,
+    pub runs: usize,
+    pub removed: isize, //instructions supprimées, négatif si la passe en a ajouté
+    pub size_before: usize, //taille du Brainfuck avant la première exécution
+    pub size_after: usize, //taille du Brainfuck après la dernière exécution
+}
+
+/* structure permettant de choisir les passes à appliquer et de les exécuter dans l'ordre des étapes */
+pub struct PassManager {
+    level: OptLevel,
+    enabled: Vec<&'static str>,
+    stats: Option<Vec<PassStats>>,
+    cell_bits: u32,
+}
+
+impl PassManager {
+    pub fn new(level: OptLevel) -> Self {
+        Self {
+            level,
+            enabled: level.passes().to_vec(),
+            stats: None,
+            cell_bits: 8,
+        }
+    }
+
+    fn find(name: &str) -> Result<&'static str> {
+        STAGES
+            .iter()
+            .flat_map(|stage| stage.iter())
+            .find(|pass| **pass == name)
+            .copied()
+            .ok_or_else(|| anyhow!("unknown pass {}", name))
+    }
+
+    /* fonction permettant d'ajouter une passe absente du niveau choisi, elle s'exécute dans son étape habituelle */
+    pub fn enable(&mut self, name: &str) -> Result<()> {
+        let name = Self::find(name)?;
+        if !self.enabled.contains(&name) {
+            self.enabled.push(name);
+        }
+        Ok(())
+    }
+
+    pub fn disable(&mut self, name: &str) -> Result<()> {
+        let name = Self::find(name)?;
+        self.enabled.retain(|pass| *pass != name);
+        Ok(())
+    }
+
+    /* les statistiques demandent de produire le Brainfuck après chaque passe, on ne les calcule que sur demande */
+    pub fn with_stats(mut self) -> Self {
+        self.stats = Some(Vec::new());
+        self
+    }
+
+    /* taille des cellules de la machine cible, les passes qui connaissent la valeur des cellules en dépendent */
+    pub fn with_cell_bits(mut self, cell_bits: u32) -> Self {
+        self.cell_bits = cell_bits;
+        self
+    }
+
+    pub fn stats(&self) -> &[PassStats] {
+        self.stats.as_deref().unwrap_or_default()
+    }
+
+    fn record(
+        &mut self,
+        name: &'static str,
+        before: &[FlattenedInstruction],
+        after: &[FlattenedInstruction],
+    ) {
+        let Some(stats) = &mut self.stats else { return };
+        let size_before = produce(before.to_vec())
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .count();
+        let size_after = produce(after.to_vec())
+            .unwrap_or_default()
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .count();
+        let removed = count(before) as isize - count(after) as isize;
+        match stats.iter_mut().find(|stat| stat.name == name) {
+            Some(stat) => {
+                stat.runs += 1;
+                stat.removed += removed;
+                stat.size_after = size_after;
+            }
+            None => stats.push(PassStats {
+                name,
+                runs: 1,
+                removed,
+                size_before,
+                size_after,
+            }),
+        }
+    }
+
+    pub fn run(&mut self, ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+        let mut ist_list = ist_list;
+        for stage in STAGES {
+            let passes = stage
+                .iter()
+                .filter(|name| self.enabled.contains(name))
+                .map(|name| make_pass(name, self.level, self.cell_bits))
+                .collect::<Vec<_>>();
+            if passes.is_empty() {
+                continue;
+            }
+            loop {
+                let start = ist_list.clone();
+                for pass in &passes {
+                    let res = pass.run(ist_list.clone());
+                    self.record(pass.name(), &ist_list, &res);
+                    ist_list = res;
                 }
-                (e, v) => {
-                    res.push_back(v);
-                    res.push_back(e);
+                if ist_list == start {
+                    break;
                 }
             }
         }
+        ist_list
     }
+}
 
-    res.into()
+/* tableau des statistiques, une ligne par passe */
+pub fn format_stats(stats: &[PassStats]) -> String {
+    let mut res = format!(
+        "{:<20} {:>6} {:>8} {:>10} {:>10}\n",
+        "pass", "runs", "removed", "bf before", "bf after"
+    );
+    for stat in stats {
+        res.push_str(&format!(
+            "{:<20} {:>6} {:>8} {:>10} {:>10}\n",
+            stat.name, stat.runs, stat.removed, stat.size_before, stat.size_after
+        ));
+    }
+    res
+}
+
+/* fonction permettant d'optimiser le code aplati avec les passes du niveau -O2 */
+pub fn optimize_consecutive(ist_list: Vec<FlattenedInstruction>) -> Vec<FlattenedInstruction> {
+    PassManager::new(OptLevel::O2).run(ist_list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter;
+    use FlattenedInstruction::*;
+
+    /* générateur pseudo-aléatoire congruentiel, suffisant pour produire des programmes de test reproductibles */
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) as usize) % n
+        }
+    }
+
+    /* instructions sans boucle qui ne s'éloignent jamais de plus de 3 cellules de la cellule de départ */
+    fn random_straight(rng: &mut Lcg, out: &mut Vec<FlattenedInstruction>, len: usize) {
+        let mut offset = 0;
+        for _ in 0..len {
+            let ist = match rng.below(8) {
+                0 => Add(rng.below(300)),
+                1 => Sub(rng.below(300)),
+                2 => SetConst(rng.below(300)),
+                3 if offset < 3 => Right(rng.below(3 - offset) + 1),
+                4 if offset > 0 => Left(rng.below(offset) + 1),
+                5 => Print,
+                6 => Read,
+                _ => Noop,
+            };
+            match ist {
+                Right(v) => offset += v,
+                Left(v) => offset -= v,
+                _ => (),
+            }
+            out.push(ist);
+        }
+        out.push(Left(offset));
+    }
+
+    /* programme aléatoire dont les boucles terminent toujours : leur corps décrémente la cellule testée et ne touche qu'aux cellules voisines */
+    fn random_program(rng: &mut Lcg) -> Vec<FlattenedInstruction> {
+        let mut res = vec![Right(8)];
+        for _ in 0..rng.below(6) + 1 {
+            let len = rng.below(8);
+            random_straight(rng, &mut res, len);
+            if rng.below(2) == 0 {
+                let mut inner = vec![Sub(1), Right(4)];
+                random_straight(rng, &mut inner, 6);
+                inner.push(Left(4));
+                res.push(Loop(inner));
+            }
+        }
+        res
+    }
+
+    fn execute(ist_list: Vec<FlattenedInstruction>) -> interpreter::Machine {
+        let code = produce(ist_list).unwrap();
+        interpreter::run(&code, b"\x05\xfe\x10\x00\x80").unwrap()
+    }
+
+    /* la passe doit laisser la bande, la tête de lecture et la sortie identiques sur des programmes aléatoires */
+    fn assert_equivalent(passes: &[&dyn Pass]) {
+        let mut rng = Lcg(0x2545f4914f6cdd1d);
+        for _ in 0..300 {
+            let program = random_program(&mut rng);
+            let expected = execute(program.clone());
+            let optimized = run_passes(program.clone(), passes);
+            let names = passes.iter().map(|pass| pass.name()).collect::<Vec<_>>();
+            let found = execute(optimized.clone());
+            assert_eq!(
+                (expected.output, expected.pointer),
+                (found.output, found.pointer),
+                "{:?} changed {:?} into {:?}",
+                names,
+                program,
+                optimized
+            );
+            assert_eq!(expected.tape, found.tape, "{:?} changed the tape", names);
+        }
+    }
+
+    #[test]
+    fn remove_noop() {
+        assert_eq!(
+            RemoveNoop.run(vec![Noop, Add(0), Loop(vec![Left(0), Sub(1)]), Right(0)]),
+            vec![Loop(vec![Sub(1)])]
+        );
+        assert_equivalent(&[&RemoveNoop]);
+    }
+
+    #[test]
+    fn combine_arithmetic() {
+        assert_eq!(
+            CombineArithmetic.run(vec![Add(5), Sub(3), Add(1), Sub(3)]),
+            vec![]
+        );
+        assert_eq!(
+            CombineArithmetic.run(vec![SetConst(2), Sub(3), SetConst(4), Add(1)]),
+            vec![SetConst(5)]
+        );
+        assert_eq!(
+            CombineArithmetic.run(vec![SetConst(2), Right(1), SetConst(4)]),
+            vec![SetConst(2), Right(1), SetConst(4)]
+        );
+        assert_equivalent(&[&CombineArithmetic]);
+    }
+
+    #[test]
+    fn combine_movement() {
+        assert_eq!(
+            CombineMovement.run(vec![Right(4), Left(6), Left(1), Right(3)]),
+            vec![]
+        );
+        assert_eq!(
+            CombineMovement.run(vec![Loop(vec![Right(2), Right(1)]), Goto(3), Goto(1)]),
+            vec![Loop(vec![Right(3)]), Goto(1)]
+        );
+        assert_equivalent(&[&CombineMovement]);
+    }
+
+    #[test]
+    fn track_values() {
+        assert_eq!(
+            TrackValues::default().run(vec![
+                Loop(vec![Sub(1)]),
+                SetConst(3),
+                SetConst(1),
+                Right(1),
+                Read,
+                SetConst(1),
+                Left(1),
+                SetConst(1),
+                Loop(vec![Right(1), SetConst(2), Left(1), SetConst(0)]),
+                SetConst(0),
+            ]),
+            vec![
+                Add(3),
+                Sub(2),
+                Right(1),
+                Read,
+                SetConst(1),
+                Left(1),
+                Loop(vec![Right(1), SetConst(2), Left(1), SetConst(0)]),
+            ]
+        );
+        //après un parcours dont la longueur dépend des données, seule la position donnée par Sync est connue
+        assert_eq!(
+            TrackValues::default().run(vec![
+                Add(1),
+                Loop(vec![Right(1)]),
+                Sync(4),
+                SetConst(2),
+                Left(4),
+                SetConst(1)
+            ]),
+            vec![
+                Add(1),
+                Loop(vec![Right(1)]),
+                Sync(4),
+                SetConst(2),
+                Left(4),
+                SetConst(1)
+            ]
+        );
+        assert_equivalent(&[&TrackValues::default()]);
+    }
+
+    #[test]
+    fn constant_synthesis() {
+        let clear = Loop(vec![Sub(1)]);
+        assert_eq!(
+            synthesize(None, 250, 256, None, MAX_FACTOR),
+            vec![clear.clone(), Sub(6)]
+        );
+        assert_eq!(
+            synthesize(Some(250), 2, 256, None, MAX_FACTOR),
+            vec![clear.clone(), Add(2)]
+        );
+        assert_eq!(synthesize(Some(10), 6, 256, None, MAX_FACTOR), vec![Sub(4)]);
+        assert_eq!(
+            synthesize(None, 65535, 1 << 16, None, MAX_FACTOR),
+            vec![clear.clone(), Sub(1)]
+        );
+        let multiplied = synthesize(Some(0), 65, 256, Some(1), MAX_FACTOR);
+        assert!(code_size(&multiplied) < 30, "{:?}", multiplied);
+        assert_eq!(
+            execute([vec![Right(3)], multiplied, vec![Print]].concat()).output,
+            vec![65]
+        );
+        assert_eq!(
+            ConstantSynthesis::default().run(vec![SetConst(65), Print, SetConst(200)]),
+            [
+                synthesize(Some(0), 65, 256, Some(1), MAX_FACTOR),
+                vec![Print],
+                synthesize(Some(65), 200, 256, Some(1), MAX_FACTOR)
+            ]
+            .concat()
+        );
+        assert_equivalent(&[&ConstantSynthesis::default()]);
+    }
+
+    #[test]
+    fn recognize_idioms() {
+        assert_eq!(
+            RecognizeIdioms.run(vec![
+                Loop(vec![Sub(1)]),
+                Loop(vec![Right(3)]),
+                Loop(vec![Right(1), Add(3), Left(2), Sub(1), Right(1), Sub(1)]),
+                Loop(vec![Add(1), Left(1), Add(2), Right(1)]),
+            ]),
+            vec![
+                Clear,
+                ScanRight(3),
+                MulAdd {
+                    offset: 1,
+                    factor: 3
+                },
+                MulAdd {
+                    offset: -1,
+                    factor: -1
+                },
+                Clear,
+                MulAdd {
+                    offset: -1,
+                    factor: -2
+                },
+                Clear,
+            ]
+        );
+        //une boucle dont le compteur change de plus de 1 ou qui se déplace reste une boucle
+        let kept = vec![
+            Loop(vec![Sub(2), Right(1), Add(1), Left(1)]),
+            Loop(vec![Sub(1), Right(1)]),
+            Loop(vec![Sub(1), Right(1), Print, Left(1)]),
+        ];
+        assert_eq!(RecognizeIdioms.run(kept.clone()), kept);
+        assert_eq!(
+            produce(RecognizeIdioms.run(vec![Loop(vec![Sub(1), Right(1), Add(3), Left(1)])]))
+                .unwrap(),
+            produce(vec![Loop(vec![Sub(1), Right(1), Add(3), Left(1)])]).unwrap()
+        );
+        assert_equivalent(&[&RecognizeIdioms]);
+    }
+
+    #[test]
+    fn defer_movement() {
+        assert_eq!(
+            DeferMovement.run(vec![
+                Right(2),
+                Add(1),
+                Left(2),
+                Add(1),
+                Right(2),
+                Add(1),
+                Print,
+                Loop(vec![
+                    Right(1),
+                    Add(1),
+                    Left(3),
+                    Sub(1),
+                    Right(3),
+                    Add(1),
+                    Left(1)
+                ]),
+            ]),
+            vec![
+                Add(1),
+                Right(2),
+                Add(1),
+                Add(1),
+                Print,
+                Loop(vec![Left(2), Sub(1), Right(3), Add(1), Add(1), Left(1)]),
+            ]
+        );
+        //une multiplication reste suivie de sa remise à 0
+        assert_eq!(
+            DeferMovement.run(vec![
+                Read,
+                MulAdd {
+                    offset: 1,
+                    factor: 1
+                },
+                Clear,
+                Left(1),
+                Add(1),
+                Right(3),
+                Print
+            ]),
+            vec![
+                Read,
+                MulAdd {
+                    offset: 1,
+                    factor: 1
+                },
+                Clear,
+                Left(1),
+                Add(1),
+                Right(3),
+                Print
+            ]
+        );
+        assert_equivalent(&[&DeferMovement]);
+    }
+
+    #[test]
+    fn dead_store() {
+        assert_eq!(
+            DeadStore.run(vec![
+                Add(2),
+                Right(1),
+                SetConst(4),
+                Left(1),
+                SetConst(1),
+                Right(1),
+                Print,
+                SetConst(3),
+                Read
+            ]),
+            vec![
+                Right(1),
+                SetConst(4),
+                Left(1),
+                SetConst(1),
+                Right(1),
+                Print,
+                Read
+            ]
+        );
+        assert_eq!(
+            DeadStore.run(vec![SetConst(1), Loop(vec![Sub(1)]), SetConst(2)]),
+            vec![SetConst(1), Loop(vec![Sub(1)]), SetConst(2)]
+        );
+        assert_equivalent(&[&DeadStore]);
+    }
+
+    #[test]
+    fn pass_manager() {
+        let program = vec![Noop, Add(2), Sub(1), Right(1), Left(1), SetConst(3)];
+        assert_eq!(PassManager::new(OptLevel::O0).run(program.clone()), program);
+        assert_eq!(
+            PassManager::new(OptLevel::O1).run(program.clone()),
+            vec![SetConst(3)]
+        );
+
+        let mut manager = PassManager::new(OptLevel::O1);
+        manager.disable("combine-arithmetic").unwrap();
+        manager.enable("track-values").unwrap();
+        let mut manager = manager.with_stats();
+        assert_eq!(manager.run(program.clone()), vec![Add(2), Sub(1), Add(2)]);
+        let stats = manager.stats();
+        assert_eq!(
+            stats.iter().map(|stat| stat.name).collect::<Vec<_>>(),
+            vec![
+                "remove-noop",
+                "combine-movement",
+                "track-values",
+                "recognize-idioms"
+            ]
+        );
+        assert_eq!(stats[1].removed, 2);
+        assert_eq!(stats[0].size_before, 11);
+        assert_eq!(stats[3].size_after, 5);
+        assert!(manager.enable("unknown").is_err());
+    }
+
+    #[test]
+    fn opposing_operations_cancel() {
+        assert_eq!(
+            optimize_consecutive(vec![Right(1), Add(1), Sub(1), Left(1)]),
+            vec![]
+        );
+        assert_eq!(
+            optimize_consecutive(vec![Add(5), Sub(3), Right(4), Noop, Left(6), Left(1)]),
+            vec![Add(2), Left(3)]
+        );
+        assert_eq!(
+            optimize_consecutive(vec![Read, Loop(vec![Sub(2), Add(1)]), Right(2), Left(2)]),
+            vec![Read, Clear]
+        );
+        assert_equivalent(&[&RemoveNoop, &CombineArithmetic, &CombineMovement]);
+        for seed in 0..50 {
+            let mut rng = Lcg(seed);
+            let program = random_program(&mut rng);
+            assert_eq!(
+                execute(program.clone()).output,
+                execute(optimize_consecutive(program)).output
+            );
+        }
+    }
 }
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::builtin::Builtin;
+use crate::function::{substitute, FunctionMapping};
+use crate::instruction::Instruction;
+use crate::layout::Layout;
+use crate::variable::Variable;
+
+/* nom réservé aux cellules de travail des built-ins, placées juste après les variables quel que soit leur ordre */
+const SCRATCH: &str = "#scratch";
+
+/* poids d'un déplacement selon la profondeur de boucle où il a lieu, une boucle étant supposée faire plusieurs tours */
+fn weight(depth: u32) -> u64 {
+    8u64.saturating_pow(depth)
+}
+
+/* structure permettant de relever les déplacements de la tête de lecture entre les cellules nommées, dans l'ordre du programme */
+#[derive(Debug, Default)]
+struct Accesses {
+    edges: HashMap<(String, String), u64>, //nombre pondéré de passages d'une cellule à l'autre
+    order: Vec<String>,                    //cellules visitées
+    fixed: bool, //le programme utilise des adresses ou des déplacements explicites, les variables ne peuvent pas bouger
+}
+
+impl Accesses {
+    fn link(&mut self, from: &str, to: &str, depth: u32) {
+        if from != to {
+            let key = if from < to {
+                (from.to_owned(), to.to_owned())
+            } else {
+                (to.to_owned(), from.to_owned())
+            };
+            *self.edges.entry(key).or_default() += weight(depth);
+        }
+    }
+
+    fn visit(&mut self, name: &str, depth: u32) {
+        if let Some(last) = self.order.last().cloned() {
+            self.link(&last, name, depth);
+        }
+        self.order.push(name.to_owned());
+    }
+
+    fn cell(&mut self, var: &Variable, layout: &Layout, depth: u32) {
+        match var {
+            Variable::Named(name) if layout.size_of(name).is_some() => self.visit(name, depth),
+            Variable::Named(_) => (), //les piles n'ont pas de cellule fixe
+            Variable::Constant(cell) => self.fixed |= *cell < layout.end(),
+        }
+    }
+
+    /* fonction permettant de parcourir le programme comme replace_function, chaque passage étant pondéré par la profondeur où il arrive
+        à la fin d'une boucle on revient à son début
+    */
+    fn collect(
+        &mut self,
+        ist_list: &[Instruction],
+        mapping: &FunctionMapping,
+        layout: &Layout,
+        depth: u32,
+        called: &mut Vec<String>,
+    ) -> Result<()> {
+        for ist in ist_list {
+            match ist {
+                Instruction::Goto(var) => self.cell(var, layout, depth),
+                Instruction::Left(_) | Instruction::Right(_) => self.fixed = true,
+                Instruction::Builtin(builtin, args) => {
+                    for arg in builtin.cell_args(args) {
+                        self.cell(arg, layout, depth);
+                    }
+                    if !matches!(builtin, Builtin::Add | Builtin::Sub | Builtin::SetConst) {
+                        self.visit(SCRATCH, depth);
+                    }
+                }
+                Instruction::Loop(inner) => {
+                    let start = self.order.len();
+                    self.collect(inner, mapping, layout, depth + 1, called)?;
+                    if let (Some(first), Some(last)) =
+                        (self.order.get(start).cloned(), self.order.last().cloned())
+                    {
+                        self.link(&last, &first, depth + 1);
+                    }
+                }
+                Instruction::FnCall(name, args) => {
+                    //un appel récursif sera refusé par replace_function
+                    if let (Some((sign, content)), false) =
+                        (mapping.get(name), called.contains(name))
+                    {
+                        let content =
+                            substitute(content, &sign.produce_args_mapping(args.to_vec()))?;
+                        called.push(name.to_owned());
+                        self.collect(&content, mapping, layout, depth, called)?;
+                        called.pop();
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/* coût d'un placement: somme des déplacements pondérés entre les cellules nommées */
+fn cost(order: &[String], layout: &Layout, edges: &HashMap<(String, String), u64>) -> u64 {
+    let mut positions = HashMap::new();
+    let mut next = 0;
+    for name in order {
+        positions.insert(name.as_str(), next);
+        next += layout.size_of(name).unwrap();
+    }
+    positions.insert(SCRATCH, next);
+    edges
+        .iter()
+        .map(|((from, to), weight)| {
+            weight * positions[from.as_str()].abs_diff(positions[to.as_str()]) as u64
+        })
+        .sum()
+}
+
+/* fonction permettant de choisir l'ordre des variables et tableaux sur la bande qui réduit les déplacements des goto
+    on part de l'ordre de déclaration et on déplace une variable à la fois tant que cela diminue le coût
+    le placement n'est pas modifié si le programme désigne des cellules par leur adresse ou se déplace lui-même
+*/
+pub fn arrange_layout(
+    ist_list: &[Instruction],
+    mapping: &FunctionMapping,
+    layout: &Layout,
+) -> Result<Layout> {
+    let mut accesses = Accesses::default();
+    accesses.collect(ist_list, mapping, layout, 0, &mut Vec::new())?;
+    if accesses.fixed {
+        return Ok(layout.clone());
+    }
+
+    let mut order = layout.declarations();
+    let mut best = cost(&order, layout, &accesses.edges);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for from in 0..order.len() {
+            for to in 0..order.len() {
+                let mut candidate = order.clone();
+                let name = candidate.remove(from);
+                candidate.insert(to, name);
+                let candidate_cost = cost(&candidate, layout, &accesses.edges);
+                if candidate_cost < best {
+                    best = candidate_cost;
+                    order = candidate;
+                    improved = true;
+                }
+            }
+        }
+    }
+    layout.arrange(&order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::function::produce_mapping;
+    use crate::layout::produce_layout;
+
+    fn arranged(source: &str) -> (Layout, Layout) {
+        let (ist, names) = Instruction::parse(source.to_owned());
+        let (ist, mapping) = produce_mapping(ist.unwrap(), names).unwrap();
+        let (ist, layout) = produce_layout(ist).unwrap();
+        let res = arrange_layout(&ist, &mapping, &layout).unwrap();
+        (layout, res)
+    }
+
+    #[test]
+    fn loop_accesses_are_placed_together() {
+        let source = "
+            var a;
+            var b;
+            var c;
+            fn bump(x) {
+                add(x, 1)
+            }
+            setconst(b, 1)
+            goto(a)
+            loop {
+                bump(c)
+                sub(a, 1)
+            }
+        ";
+        let (declared, res) = arranged(source);
+        let position = |layout: &Layout, name: &str| {
+            layout.resolve(&Variable::Named(name.to_owned())).unwrap()
+        };
+        assert_eq!(position(&declared, "c"), 2);
+        assert_eq!(position(&res, "a"), 1);
+        assert_eq!(position(&res, "a").abs_diff(position(&res, "c")), 1);
+        assert_eq!(res.end(), declared.end());
+    }
+
+    #[test]
+    fn explicit_addresses_keep_declaration_order() {
+        let (declared, res) = arranged("var a; var b; var c; goto(c) loop { goto(a) right(1) }");
+        assert_eq!(res.map(), declared.map());
+        assert_eq!(res.map(), "0\t1\ta\tu8\n1\t1\tb\tu8\n2\t1\tc\tu8\n");
+    }
+}
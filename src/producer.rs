@@ -1,52 +1,309 @@
+use anyhow::{anyhow, Result};
+
 use crate::function::FlattenedInstruction;
 
 /* fonction permettant de transformer chaque instruction en chaine de caractère finale en Brainfuck
-    ici on suppose les goto déjà subsituter en Left ou Right
+    ici on suppose les goto déjà subsituter en Left ou Right et les fonctions standards déjà développées,
+    une multiplication ne pouvant être produite qu'avec la remise à 0 qui la suit, voir produce
 */
-pub fn produce_string(ist: FlattenedInstruction) -> String {
+pub fn produce_string(ist: FlattenedInstruction) -> Result<String> {
     match ist {
-        FlattenedInstruction::Noop => String::default(),
-        FlattenedInstruction::Print => String::from(","),
+        FlattenedInstruction::Noop | FlattenedInstruction::Sync(_) => Ok(String::default()),
+        FlattenedInstruction::Print => Ok(String::from(".")),
+        FlattenedInstruction::Read => Ok(String::from(",")),
         FlattenedInstruction::Add(val) => {
             let mut s = String::new();
             s.push_str(&"+".repeat(val));
-            s.push_str("\n");
-            s
+            s.push('\n');
+            Ok(s)
         }
         FlattenedInstruction::Sub(val) => {
             let mut s = String::new();
             s.push_str(&"-".repeat(val));
-            s.push_str("\n");
-            s
+            s.push('\n');
+            Ok(s)
         }
         FlattenedInstruction::Left(val) => {
             let mut s = String::new();
             s.push_str(&"<".repeat(val));
-            s.push_str("\n");
-            s
+            s.push('\n');
+            Ok(s)
         }
         FlattenedInstruction::Right(val) => {
             let mut s = String::new();
             s.push_str(&">".repeat(val));
-            s.push_str("\n");
-            s
+            s.push('\n');
+            Ok(s)
         }
         FlattenedInstruction::SetConst(val) => {
             let mut s = String::from("[-]");
             s.push_str(&"+".repeat(val));
-            s
+            Ok(s)
         }
         FlattenedInstruction::Loop(inner) => {
             let mut s = String::from("[");
-            s.push_str(
-                &inner
-                    .into_iter()
-                    .map(|ist| produce_string(ist))
-                    .collect::<String>(),
-            );
-            s.push_str("]");
-            s
-        }
-        _ => unreachable!(),
+            s.push_str(&produce(inner)?);
+            s.push(']');
+            Ok(s)
+        }
+        FlattenedInstruction::Clear => Ok(String::from("[-]")),
+        FlattenedInstruction::Annotation(context) => Ok(format!("\n{}\n", context.describe())),
+        FlattenedInstruction::ScanLeft(stride) => produce_string(FlattenedInstruction::Loop(vec![
+            FlattenedInstruction::Left(stride),
+        ])),
+        FlattenedInstruction::ScanRight(stride) => {
+            produce_string(FlattenedInstruction::Loop(vec![
+                FlattenedInstruction::Right(stride),
+            ]))
+        }
+        FlattenedInstruction::MulAdd { .. } => Err(anyhow!("multiplication without clear")),
+        FlattenedInstruction::Goto(_) => {
+            Err(anyhow!("goto must be lowered before producing brainfuck"))
+        }
+        FlattenedInstruction::Builtin(builtin, _) => Err(anyhow!(
+            "builtin {} must be expanded before producing brainfuck",
+            builtin.name()
+        )),
+    }
+}
+
+/* déplacement de la tête de lecture de from à to, relativement à la cellule de départ de la boucle */
+fn shift(from: isize, to: isize) -> FlattenedInstruction {
+    if to >= from {
+        FlattenedInstruction::Right((to - from) as usize)
+    } else {
+        FlattenedInstruction::Left((from - to) as usize)
+    }
+}
+
+/* fonction permettant de retrouver la boucle Brainfuck correspondant à une suite de multiplications */
+fn produce_multiplication(multiplications: Vec<(isize, isize)>) -> Result<String> {
+    let mut inner = vec![FlattenedInstruction::Sub(1)];
+    let mut position = 0;
+    for (offset, factor) in multiplications {
+        inner.push(shift(position, offset));
+        inner.push(if factor >= 0 {
+            FlattenedInstruction::Add(factor as usize)
+        } else {
+            FlattenedInstruction::Sub(factor.unsigned_abs())
+        });
+        position = offset;
+    }
+    inner.push(shift(position, 0));
+    produce_string(FlattenedInstruction::Loop(inner))
+}
+
+/* fonction permettant de transformer une suite d'instructions en Brainfuck
+    les multiplications qui précèdent une remise à 0 sont regroupées dans la boucle dont elles proviennent
+*/
+pub fn produce(ist_list: Vec<FlattenedInstruction>) -> Result<String> {
+    let mut res = String::new();
+    let mut multiplications = Vec::new();
+    for ist in ist_list {
+        match ist {
+            FlattenedInstruction::MulAdd { offset, factor } => {
+                multiplications.push((offset, factor))
+            }
+            FlattenedInstruction::Clear if !multiplications.is_empty() => res.push_str(
+                &produce_multiplication(std::mem::take(&mut multiplications))?,
+            ),
+            _ if !multiplications.is_empty() => {
+                return Err(anyhow!("multiplication without clear"))
+            }
+            e => res.push_str(&produce_string(e)?),
+        }
+    }
+    if multiplications.is_empty() {
+        Ok(res)
+    } else {
+        Err(anyhow!("multiplication without clear"))
+    }
+}
+
+/* mise en forme du Brainfuck produit:
+    Raw garde la sortie de produce, Minified tient sur une ligne, Wrapped coupe les lignes à la largeur donnée
+    et Pretty indente le contenu des boucles en mettant chaque crochet sur sa propre ligne
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Raw,
+    Minified,
+    Wrapped,
+    Pretty,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name {
+            "raw" => Ok(Self::Raw),
+            "minified" => Ok(Self::Minified),
+            "wrapped" => Ok(Self::Wrapped),
+            "pretty" => Ok(Self::Pretty),
+            _ => Err(anyhow!("unknown output format {}", name)),
+        }
+    }
+}
+
+/* nombre d'espaces par niveau de boucle en mode Pretty */
+pub(crate) const INDENT: usize = 4;
+
+/* fonction permettant d'ajouter des instructions à la sortie en lignes d'au plus width caractères, indentation comprise */
+fn push_lines(res: &mut String, commands: &str, indent: usize, width: usize) {
+    let chars = commands.chars().collect::<Vec<_>>();
+    for line in chars.chunks(width.saturating_sub(indent).max(1)) {
+        res.push_str(&" ".repeat(indent));
+        res.extend(line);
+        res.push('\n');
+    }
+}
+
+/* les huit commandes du Brainfuck */
+pub const COMMANDS: &str = "+-<>[].,";
+
+/* fonction permettant de mettre en forme le Brainfuck produit, les caractères qui ne sont pas des commandes sont retirés sauf en mode Raw
+    en mode Pretty on garde les lignes de commentaire produites par --annotate, au niveau d'indentation de la boucle où elles se trouvent
+*/
+pub fn format_code(code: &str, format: Format, width: usize) -> String {
+    format_with(code, format, width, COMMANDS)
+}
+
+/* même chose que format_code pour un dialecte dont les commandes sont les caractères de command_set */
+pub fn format_with(code: &str, format: Format, width: usize, command_set: &str) -> String {
+    let commands = code
+        .chars()
+        .filter(|c| command_set.contains(*c))
+        .collect::<String>();
+    match format {
+        Format::Raw => code.to_owned(),
+        Format::Minified => commands,
+        Format::Wrapped => {
+            let mut res = String::new();
+            push_lines(&mut res, &commands, 0, width);
+            res
+        }
+        Format::Pretty => {
+            let mut res = String::new();
+            let mut line = String::new();
+            let mut depth = 0;
+            for code_line in code.lines() {
+                //une ligne sans commande non vide est un commentaire
+                if !code_line.chars().any(|c| command_set.contains(c)) {
+                    if !code_line.trim().is_empty() {
+                        push_lines(&mut res, &line, depth * INDENT, width);
+                        line.clear();
+                        push_lines(&mut res, code_line.trim(), depth * INDENT, width);
+                    }
+                    continue;
+                }
+                for c in code_line.chars().filter(|c| command_set.contains(*c)) {
+                    match c {
+                        '[' | ']' => {
+                            push_lines(&mut res, &line, depth * INDENT, width);
+                            line.clear();
+                            if c == ']' {
+                                depth -= 1;
+                            }
+                            push_lines(&mut res, &c.to_string(), depth * INDENT, width);
+                            if c == '[' {
+                                depth += 1;
+                            }
+                        }
+                        c => line.push(c),
+                    }
+                }
+            }
+            push_lines(&mut res, &line, depth * INDENT, width);
+            res
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtin::Builtin;
+    use crate::function::FlattenedInstruction::*;
+
+    #[test]
+    fn idioms_lower_to_brainfuck() {
+        assert_eq!(produce_string(Clear).unwrap(), "[-]");
+        assert_eq!(produce_string(ScanLeft(2)).unwrap(), "[<<\n]");
+        assert_eq!(produce_string(ScanRight(1)).unwrap(), "[>\n]");
+        assert_eq!(
+            produce(vec![
+                MulAdd {
+                    offset: 2,
+                    factor: 3
+                },
+                MulAdd {
+                    offset: -1,
+                    factor: -1
+                },
+                Clear
+            ])
+            .unwrap(),
+            "[-\n>>\n+++\n<<<\n-\n>\n]"
+        );
+        assert!(produce_string(MulAdd {
+            offset: 1,
+            factor: 1
+        })
+        .is_err());
+        assert!(produce(vec![MulAdd {
+            offset: 1,
+            factor: 1
+        }])
+        .is_err());
+        assert!(produce(vec![
+            MulAdd {
+                offset: 1,
+                factor: 1
+            },
+            Print,
+            Clear
+        ])
+        .is_err());
+        assert!(produce_string(Goto(2)).is_err());
+        assert!(produce_string(Builtin(Builtin::PrintNum, vec![0])).is_err());
+    }
+
+    #[test]
+    fn output_formats() {
+        let code = "++\n[-\n>+<]>.";
+        assert_eq!(format_code(code, Format::Raw, 80), code);
+        assert_eq!(format_code(code, Format::Minified, 80), "++[->+<]>.");
+        assert_eq!(format_code(code, Format::Wrapped, 4), "++[-\n>+<]\n>.\n");
+        assert_eq!(
+            format_code("+[->[-]<]>", Format::Pretty, 80),
+            "+\n[\n    ->\n    [\n        -\n    ]\n    <\n]\n>\n"
+        );
+        assert_eq!(
+            format_code("[++++++]", Format::Pretty, 8),
+            "[\n    ++++\n    ++\n]\n"
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        use crate::builtin::expand_builtin;
+        use crate::code_checker::{transform_goto, Outputpointer};
+        use crate::function::{produce_mapping, replace_function};
+        use crate::instruction::Instruction;
+        use crate::layout::produce_layout;
+        use std::collections::HashSet;
+
+        let source = "var a;\nvar b;\nfn inner(x) {\n    add(x, 2)\n}\nfn outer(x) {\n    inner(x)\n    print()\n}\nloop {\n    outer(b)\n    sub(a, 1)\n}\n";
+        let (ist, names) = Instruction::parse(source.to_owned());
+        let (ist, mapping) = produce_mapping(ist.unwrap(), names).unwrap();
+        let (ist, layout) = produce_layout(ist).unwrap();
+        let ist = replace_function(&ist, &mapping, &mut HashSet::new(), &layout).unwrap();
+        let ist = expand_builtin(ist, &layout).unwrap();
+        let (ist, _) = transform_goto(ist, Outputpointer::default());
+        assert_eq!(
+            format_code(&produce(ist.unwrap()).unwrap(), Format::Pretty, 80),
+            "line 10 at cell 0\n[\n    line 11 at cell 0\n    line 7 in outer at cell 0\n    \
+             line 4 in inner in outer at cell 0\n    >++\n    line 8 in outer at cell 1\n    .\n    \
+             line 12 at cell 1\n    <-\n]\n"
+        );
     }
 }
@@ -1,12 +1,13 @@
 use std::collections::HashMap;
 
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, Result};
 use pest::iterators::Pair;
+use serde::{Deserialize, Serialize};
 
 use crate::instruction::Rule;
 
 /* structure permettant de représenter les arguments des instructions pouvant être des littéraux dans le cas d'une fonction */
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum Variable {
     Named(String),
     Constant(usize),
@@ -43,24 +44,24 @@ impl Variable {
         }
     }
 
-    pub fn copy(&self) -> Self {
+    /* quantité d'une instruction, seule goto accepte une variable qui désigne alors sa cellule */
+    pub fn amount(&self) -> Result<usize> {
         match self {
-            Self::Named(name) => Self::Named(name.to_owned()),
-            Self::Constant(u) => Self::Constant(*u),
+            Self::Constant(value) => Ok(*value),
+            Self::Named(name) => Err(anyhow!(
+                "expected a constant amount, found variable {}",
+                name
+            )),
         }
     }
 
-    /* fonction permettant de substituer une variable grâce à une table de correspondance */
+    /* fonction permettant de substituer une variable grâce à une table de correspondance
+        un nom absent de la table est gardé, il peut s'agir d'une variable déclarée avec var
+    */
     pub fn try_substitute(&self, mapping: &HashMap<String, Variable>) -> Result<Self> {
         match self {
             Self::Constant(u) => Ok(Self::Constant(*u)),
-            Self::Named(name) => {
-                let n = mapping
-                    .get(name)
-                    .context("try to use an undeclared variable")?;
-
-                Ok(n.clone())
-            }
+            Self::Named(name) => Ok(mapping.get(name).cloned().unwrap_or_else(|| self.clone())),
         }
     }
 }
@@ -0,0 +1,308 @@
+use anyhow::{anyhow, Result};
+
+use crate::backend::TAPE_SIZE;
+use crate::function::FlattenedInstruction;
+use crate::interpreter::Machine;
+use crate::offset::{Block, OffsetInstruction};
+
+/* instructions du bytecode: les opérations portent la cellule qu'elles modifient relativement à la tête de lecture,
+    les soustractions sont des additions modulo 256 et chaque saut connaît déjà sa destination
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add { offset: isize, value: u8 },
+    Set { offset: isize, value: u8 },
+    Move(isize),
+    MulAdd { offset: isize, factor: u8 },
+    Scan(isize),
+    Print,
+    Read,
+    JumpIfZero(usize), //vers l'instruction qui suit le JumpIfNotZero correspondant
+    JumpIfNotZero(usize), //vers l'instruction qui suit le JumpIfZero correspondant
+}
+
+/* machine virtuelle exécutant le bytecode produit à partir des instructions aplaties */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vm {
+    code: Vec<Op>,
+    reach: usize, //plus grand décalage vers la droite utilisé par une opération
+}
+
+impl Vm {
+    pub fn new(ist_list: &[FlattenedInstruction]) -> Self {
+        let mut vm = Self {
+            code: Vec::new(),
+            reach: 0,
+        };
+        vm.compile(ist_list);
+        vm.reach = vm
+            .code
+            .iter()
+            .map(|op| match op {
+                Op::Add { offset, .. } | Op::Set { offset, .. } | Op::MulAdd { offset, .. } => {
+                    (*offset).max(0) as usize
+                }
+                _ => 0,
+            })
+            .max()
+            .unwrap_or(0);
+        vm
+    }
+
+    #[inline]
+    pub fn code(&self) -> &[Op] {
+        &self.code
+    }
+
+    /* fonction permettant d'ajouter une opération sur une cellule, fusionnée avec la précédente si elle porte sur la même cellule */
+    fn push(&mut self, op: Op) {
+        let same_cell = match (self.code.last(), op) {
+            (
+                Some(Op::Add { offset, .. } | Op::Set { offset, .. }),
+                Op::Add { offset: new, .. } | Op::Set { offset: new, .. },
+            ) => *offset == new,
+            _ => false,
+        };
+        match (self.code.last_mut(), op) {
+            (Some(Op::Add { value, .. } | Op::Set { value, .. }), Op::Add { value: added, .. })
+                if same_cell =>
+            {
+                *value = value.wrapping_add(added)
+            }
+            (Some(last), Op::Set { .. }) if same_cell => *last = op,
+            _ => self.code.push(op),
+        }
+    }
+
+    /* fonction permettant de traduire les instructions en bytecode, chaque suite d'opérations sans entrée-sortie ni boucle
+        est mise sous forme à décalages pour ne déplacer la tête de lecture qu'une fois
+    */
+    fn compile(&mut self, ist_list: &[FlattenedInstruction]) {
+        let mut i = 0;
+        while i < ist_list.len() {
+            if Block::accepts(&ist_list[i]) {
+                let start = i;
+                while i < ist_list.len() && Block::accepts(&ist_list[i]) {
+                    i += 1;
+                }
+                let block = Block::from_flat(&ist_list[start..i]).unwrap();
+                for ist in &block.ist_list {
+                    self.push(match *ist {
+                        OffsetInstruction::Add { offset, n } => Op::Add {
+                            offset,
+                            value: (n % 256) as u8,
+                        },
+                        OffsetInstruction::Sub { offset, n } => Op::Add {
+                            offset,
+                            value: ((n % 256) as u8).wrapping_neg(),
+                        },
+                        OffsetInstruction::SetConst { offset, value } => Op::Set {
+                            offset,
+                            value: (value % 256) as u8,
+                        },
+                        OffsetInstruction::Clear { offset } => Op::Set { offset, value: 0 },
+                    });
+                }
+                if block.end != 0 {
+                    self.code.push(Op::Move(block.end));
+                }
+                continue;
+            }
+
+            match &ist_list[i] {
+                FlattenedInstruction::Print => self.code.push(Op::Print),
+                FlattenedInstruction::Read => self.code.push(Op::Read),
+                FlattenedInstruction::MulAdd { offset, factor } => self.code.push(Op::MulAdd {
+                    offset: *offset,
+                    factor: factor.rem_euclid(256) as u8,
+                }),
+                FlattenedInstruction::ScanLeft(stride) => {
+                    self.code.push(Op::Scan(-(*stride as isize)))
+                }
+                FlattenedInstruction::ScanRight(stride) => {
+                    self.code.push(Op::Scan(*stride as isize))
+                }
+                FlattenedInstruction::Loop(inner) => {
+                    let open = self.code.len();
+                    self.code.push(Op::JumpIfZero(0));
+                    self.compile(inner);
+                    self.code.push(Op::JumpIfNotZero(open + 1));
+                    self.code[open] = Op::JumpIfZero(self.code.len());
+                }
+                FlattenedInstruction::Sync(_) | FlattenedInstruction::Annotation(_) => (),
+                _ => unreachable!(), //les goto et built-ins ont disparu, le reste est accepté par Block
+            }
+            i += 1;
+        }
+    }
+
+    /* fonction permettant d'exécuter le bytecode avec la même machine que l'interpréteur naïf: cellules de 8 bits, la fin de l'entrée donne 0
+        la bande s'agrandit pour que toute cellule atteinte par une opération existe
+    */
+    pub fn run(&self, input: &[u8]) -> Result<Machine> {
+        let mut tape = vec![0u8; TAPE_SIZE.max(self.reach + 1)];
+        let mut p = 0usize;
+        let mut output = Vec::new();
+        let mut input = input.iter();
+        let mut pc = 0;
+
+        let cell = |p: usize, offset: isize| {
+            p.checked_add_signed(offset)
+                .ok_or_else(|| anyhow!("move left of the first cell"))
+        };
+
+        while let Some(op) = self.code.get(pc) {
+            match *op {
+                Op::Add { offset, value } => {
+                    let c = cell(p, offset)?;
+                    tape[c] = tape[c].wrapping_add(value);
+                }
+                Op::Set { offset, value } => tape[cell(p, offset)?] = value,
+                Op::MulAdd { offset, factor } => {
+                    let c = cell(p, offset)?;
+                    tape[c] = tape[c].wrapping_add(tape[p].wrapping_mul(factor));
+                }
+                Op::Move(offset) => {
+                    p = cell(p, offset)?;
+                    if p + self.reach >= tape.len() {
+                        tape.resize((p + self.reach + 1).max(tape.len() * 2), 0);
+                    }
+                }
+                Op::Scan(stride) => {
+                    while tape[p] != 0 {
+                        p = cell(p, stride)?;
+                        if p + self.reach >= tape.len() {
+                            tape.resize((p + self.reach + 1).max(tape.len() * 2), 0);
+                        }
+                    }
+                }
+                Op::Print => output.push(tape[p]),
+                Op::Read => tape[p] = input.next().copied().unwrap_or(0),
+                Op::JumpIfZero(target) if tape[p] == 0 => {
+                    pc = target;
+                    continue;
+                }
+                Op::JumpIfNotZero(target) if tape[p] != 0 => {
+                    pc = target;
+                    continue;
+                }
+                Op::JumpIfZero(_) | Op::JumpIfNotZero(_) => (),
+            }
+            pc += 1;
+        }
+
+        Ok(Machine {
+            tape,
+            pointer: p,
+            output,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builtin::{expand_builtin, Builtin};
+    use crate::code_checker::{transform_goto, Outputpointer};
+    use crate::decompiler::parse_brainfuck;
+    use crate::interpreter;
+    use crate::layout::Layout;
+    use crate::optimizer::{optimize_consecutive, OptLevel, PassManager};
+    use crate::producer::produce;
+    use std::time::Instant;
+    use FlattenedInstruction::*;
+
+    #[test]
+    fn bytecode_jumps() {
+        let vm = Vm::new(&[
+            Add(1),
+            Loop(vec![Right(2), Sub(1), Left(2), Loop(vec![Print])]),
+        ]);
+        assert_eq!(
+            vm.code(),
+            [
+                Op::Add {
+                    offset: 0,
+                    value: 1
+                },
+                Op::JumpIfZero(7),
+                Op::Add {
+                    offset: 2,
+                    value: 255
+                },
+                Op::JumpIfZero(6),
+                Op::Print,
+                Op::JumpIfNotZero(4),
+                Op::JumpIfNotZero(2),
+            ]
+        );
+    }
+
+    #[test]
+    fn vm_matches_interpreter() {
+        //lit deux nombres, affiche leur produit, leur quotient et leur reste
+        let ist_list = vec![
+            Goto(0),
+            Builtin(Builtin::ReadNum, vec![0]),
+            Builtin(Builtin::ReadNum, vec![1]),
+            Builtin(Builtin::Mul, vec![0, 1, 2]),
+            Builtin(Builtin::PrintNum, vec![2]),
+            Builtin(Builtin::DivMod, vec![0, 1, 3, 4]),
+            Builtin(Builtin::PrintNum, vec![3]),
+            Builtin(Builtin::PrintNum, vec![4]),
+        ];
+        let ist_list = expand_builtin(ist_list, &Layout::default()).unwrap();
+        let (res, _) = transform_goto(ist_list, Outputpointer::default());
+        let ist_list = optimize_consecutive(res.unwrap());
+        for input in ["12 7\n", "200 3\n", "0 9\n"] {
+            let expected =
+                interpreter::run(&produce(ist_list.clone()).unwrap(), input.as_bytes()).unwrap();
+            let machine = Vm::new(&ist_list).run(input.as_bytes()).unwrap();
+            assert_eq!(machine.output, expected.output);
+            assert_eq!(machine.pointer, expected.pointer);
+            assert_eq!(machine.tape[..100], expected.tape[..100]);
+        }
+    }
+
+    /* temps d'exécution du programme par l'interpréteur naïf et par la machine virtuelle, les sorties devant être identiques */
+    fn speedup(code: &str) -> f64 {
+        let ist_list = PassManager::new(OptLevel::O2).run(parse_brainfuck(code).unwrap());
+        let brainfuck = produce(ist_list.clone()).unwrap();
+        let vm = Vm::new(&ist_list);
+
+        let start = Instant::now();
+        let expected = interpreter::run(&brainfuck, &[]).unwrap();
+        let naive = start.elapsed();
+        let start = Instant::now();
+        let machine = vm.run(&[]).unwrap();
+        let fast = start.elapsed();
+
+        assert_eq!(machine.output, expected.output);
+        let ratio = naive.as_secs_f64() / fast.as_secs_f64();
+        eprintln!(
+            "interpreter: {:?}, vm: {:?}, speedup: {:.1}x",
+            naive, fast, ratio
+        );
+        ratio
+    }
+
+    /* mesure du gain de la machine virtuelle sur l'interpréteur naïf, à lancer avec:
+        cargo test --release vm_speedup -- --ignored --nocapture
+        l'objectif d'un facteur 10 est atteint quand les boucles les plus exécutées deviennent des idiomes
+        une boucle qui n'en est pas un ne gagne que la fusion des commandes en opérations, ici 19 commandes pour 8 opérations par tour,
+        le gain est donc borné par ce rapport (2 à 3 fois) sans compilation à la volée
+    */
+    #[test]
+    #[ignore]
+    fn vm_speedup() {
+        //trois boucles imbriquées de 255 tours, la plus interne (+--) devenant une remise à zéro
+        assert!(speedup("-[>-[>-[+--]<-]<-]>>>+.") >= 10.0);
+        //trois boucles imbriquées de 200 tours, la plus interne n'étant pas un idiome reconnu par l'optimiseur
+        assert!(
+            speedup(
+                "++++++++++[>++++++++++++++++++++<-]>[>++++++++++[>++++++++++++++++++++<-]>\
+                 [>++++++++++[>++++++++++++++++++++<-]>[>+>++<<->>[-<+>]<<]<<-]<<-]>>>>>>."
+            ) >= 1.5
+        );
+    }
+}